@@ -0,0 +1,164 @@
+use std::path::Path;
+
+pub const USAGE: &str = "\
+Usage: hecto [OPTIONS] [+LINE] [FILE[:LINE[:COLUMN]]]...
+
+Options:
+  -R, --readonly  Open the files read-only; Ctrl-S saves a copy
+  -o, --stdout    Write the buffer read from stdin, or else the current
+                  one, to stdout on exit
+  -h, --help      Print this help and exit
+  -V, --version   Print the version and exit
+
++LINE opens the file after it at that line. A FILE of - reads stdin, so
+hecto can sit in a pipeline: cmd | hecto -o - | cmd";
+
+/// A file named on the command line, with the 1-based line and column to
+/// open it at.
+#[derive(PartialEq, Debug)]
+pub struct FileArg {
+    pub path: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+#[derive(PartialEq, Debug, Default)]
+pub struct Args {
+    pub files: Vec<FileArg>,
+    pub read_only: bool,
+    pub to_stdout: bool,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Action {
+    Edit(Args),
+    Help,
+    Version,
+}
+
+impl Args {
+    pub fn parse<I>(args: I) -> Result<Action, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut parsed = Self::default();
+        let mut line = None;
+        let mut only_files = false;
+        for arg in args {
+            if only_files || arg == "-" || !arg.starts_with(['-', '+']) {
+                if arg == "-" && parsed.files.iter().any(|file| file.path == "-") {
+                    return Err("stdin (-) can only be read once".to_string());
+                }
+                let mut file = FileArg::from(arg);
+                if line.is_some() {
+                    file.line = line.take();
+                    file.column = None;
+                }
+                parsed.files.push(file);
+                continue;
+            }
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(Action::Help),
+                "-V" | "--version" => return Ok(Action::Version),
+                "-R" | "--readonly" => parsed.read_only = true,
+                "-o" | "--stdout" => parsed.to_stdout = true,
+                "--" => only_files = true,
+                _ if arg.starts_with('+') => {
+                    line = Some(
+                        arg[1..]
+                            .parse()
+                            .map_err(|_| format!("invalid line number: {}", arg))?,
+                    );
+                }
+                _ => return Err(format!("unknown option: {}", arg)),
+            }
+        }
+        if let Some(line) = line {
+            return Err(format!("+{} must be followed by a file", line));
+        }
+        Ok(Action::Edit(parsed))
+    }
+}
+
+impl From<String> for FileArg {
+    /// Splits off a `:LINE` or `:LINE:COLUMN` suffix, as printed by compilers
+    /// and grep, unless a file with the full name exists.
+    fn from(arg: String) -> Self {
+        let plain = Self {
+            path: arg.clone(),
+            line: None,
+            column: None,
+        };
+        if Path::new(&arg).exists() {
+            return plain;
+        }
+        let trimmed = arg.strip_suffix(':').unwrap_or(&arg);
+        match split_number(trimmed) {
+            Some((rest, number)) => match split_number(rest) {
+                Some((path, line)) => Self {
+                    path: path.to_string(),
+                    line: Some(line),
+                    column: Some(number),
+                },
+                None => Self {
+                    path: rest.to_string(),
+                    line: Some(number),
+                    column: None,
+                },
+            },
+            None => plain,
+        }
+    }
+}
+
+/// Splits `name:number` into its parts.
+fn split_number(arg: &str) -> Option<(&str, usize)> {
+    let (rest, number) = arg.rsplit_once(':')?;
+    if rest.is_empty() {
+        return None;
+    }
+    Some((rest, number.parse().ok()?))
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Action, String> {
+        Args::parse(args.iter().map(|arg| (*arg).to_string()))
+    }
+
+    #[test]
+    fn test_parse_positions() {
+        let action = parse(&["-R", "+42", "a.rs", "b.rs:3:7", "c.rs:5:", "-"]).unwrap();
+        let args = if let Action::Edit(args) = action {
+            args
+        } else {
+            panic!("expected files to edit");
+        };
+        assert!(args.read_only);
+        let positions: Vec<(&str, Option<usize>, Option<usize>)> = args
+            .files
+            .iter()
+            .map(|file| (file.path.as_str(), file.line, file.column))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                ("a.rs", Some(42), None),
+                ("b.rs", Some(3), Some(7)),
+                ("c.rs", Some(5), None),
+                ("-", None, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse(&["--help", "x"]), Ok(Action::Help));
+        assert!(parse(&["--frobnicate"]).is_err());
+        assert!(parse(&["+x", "a.rs"]).is_err());
+        assert!(parse(&["a.rs", "+3"]).is_err());
+        assert!(parse(&["-", "a.rs", "-"]).is_err());
+    }
+}
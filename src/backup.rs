@@ -0,0 +1,164 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+/// The length of a `YYYYMMDDTHHMMSSZ` timestamp.
+const TIMESTAMP_LEN: usize = 16;
+
+/// How the previous version of a file is kept when a save overwrites it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Backup {
+    pub enabled: bool,
+    /// Where timestamped backups go. Without one, a single `file~` is kept
+    /// next to the file.
+    pub dir: Option<PathBuf>,
+    /// How many timestamped backups of each file to keep, or 0 for all.
+    pub keep: usize,
+}
+
+impl Default for Backup {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: None,
+            keep: 10,
+        }
+    }
+}
+
+impl Backup {
+    /// Copies the current contents of `path`, if there are any, to its
+    /// backup.
+    pub fn save(&self, path: &Path) -> Result<(), io::Error> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let target = match fs::canonicalize(path) {
+            Ok(target) => target,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        if let Some(dir) = &self.dir {
+            fs::create_dir_all(dir)?;
+            let prefix = mangle(&target);
+            let backup = dir.join(format!("{}.{}~", prefix, timestamp(SystemTime::now())));
+            // Of several saves within a second, the oldest version is the
+            // one worth keeping.
+            if !backup.exists() {
+                fs::copy(&target, backup)?;
+            }
+            prune(dir, &prefix, self.keep)
+        } else {
+            let mut name = target.file_name().unwrap_or_default().to_os_string();
+            name.push("~");
+            fs::copy(&target, target.with_file_name(name)).map(|_| ())
+        }
+    }
+}
+
+/// Turns the full path of a file into a single file name, the way vim does,
+/// so files with the same name in different directories keep apart.
+fn mangle(path: &Path) -> String {
+    path.to_string_lossy().replace('/', "%")
+}
+
+/// Removes all but the newest `keep` backups starting with `prefix`.
+fn prune(dir: &Path, prefix: &str, keep: usize) -> Result<(), io::Error> {
+    if keep == 0 {
+        return Ok(());
+    }
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        let stamp = name
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_prefix('.'))
+            .and_then(|rest| rest.strip_suffix('~'));
+        if stamp.map_or(false, |stamp| stamp.len() == TIMESTAMP_LEN) {
+            backups.push(name);
+        }
+    }
+    // The timestamps sort in chronological order.
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep);
+    for name in backups.iter().take(excess) {
+        fs::remove_file(dir.join(name))?;
+    }
+    Ok(())
+}
+
+/// Formats `time` as a UTC `YYYYMMDDTHHMMSSZ` timestamp.
+#[allow(clippy::integer_arithmetic)]
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (year, month, day) = civil_from_days(seconds / SECONDS_PER_DAY);
+    let time_of_day = seconds % SECONDS_PER_DAY;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60
+    )
+}
+
+/// The Gregorian date `days` after 1970-01-01, after Howard Hinnant's
+/// `civil_from_days`.
+#[allow(clippy::integer_arithmetic)]
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+    use crate::tempdir::TempDir;
+    use std::time::Duration;
+
+    #[test]
+    fn test_backups_are_pruned() {
+        assert_eq!(timestamp(UNIX_EPOCH), "19700101T000000Z");
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(1_709_210_096)),
+            "20240229T123456Z"
+        );
+
+        let dir = TempDir::new("backup");
+        let names = [
+            "%a.20240101T000000Z~",
+            "%a.20240102T000000Z~",
+            "%a.20240103T000000Z~",
+            "%a.b.20240101T000000Z~",
+        ];
+        for name in &names {
+            dir.write(name, "");
+        }
+        prune(dir.path(), "%a", 2).unwrap();
+        let mut left: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        left.sort();
+        assert_eq!(left, &names[1..]);
+    }
+}
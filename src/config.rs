@@ -0,0 +1,167 @@
+use crate::keymap::{self, Command, Keymap};
+use crate::Backup;
+use crate::Key;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// User settings, read from `$XDG_CONFIG_HOME/hecto/config` (or
+/// `~/.config/hecto/config`). The file holds one `option = value` pair per
+/// line; blank lines and lines starting with `#` are ignored.
+///
+/// `keymap = emacs` starts over from the emacs bindings, `bind = Ctrl-K
+/// Ctrl-C save` binds a key or chord to a command and `unbind = Ctrl-Q`
+/// removes a binding.
+pub struct Config {
+    pub auto_pairs: bool,
+    pub backup: Backup,
+    pub keymap: Keymap,
+    /// Whether editing is modal, as in vim.
+    pub modal: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            auto_pairs: true,
+            backup: Backup::default(),
+            keymap: Keymap::default(),
+            modal: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Self, String> {
+        let path = if let Some(path) = Self::path() {
+            path
+        } else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(format!("{}: {}", path.display(), error)),
+        }
+    }
+    fn path() -> Option<PathBuf> {
+        let dir = if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+            PathBuf::from(dir)
+        } else {
+            PathBuf::from(env::var_os("HOME")?).join(".config")
+        };
+        Some(dir.join("hecto").join("config"))
+    }
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let line_number = index.saturating_add(1);
+            let mut parts = line.splitn(2, '=');
+            let (option, value) = match (parts.next(), parts.next()) {
+                (Some(option), Some(value)) => (option.trim(), value.trim()),
+                _ => return Err(format!("line {}: expected `option = value`", line_number)),
+            };
+            match option {
+                "auto_pairs" => config.auto_pairs = parse_bool(value, line_number)?,
+                "backup" => config.backup.enabled = parse_bool(value, line_number)?,
+                "modal" => config.modal = parse_bool(value, line_number)?,
+                "backup_dir" => {
+                    config.backup.enabled = true;
+                    config.backup.dir = Some(expand_home(value));
+                }
+                "backup_keep" => {
+                    config.backup.keep = value.parse().map_err(|_| {
+                        format!("line {}: expected a number, got `{}`", line_number, value)
+                    })?;
+                }
+                "keymap" => {
+                    config.keymap = match value {
+                        "default" => Keymap::default(),
+                        "emacs" => Keymap::emacs(),
+                        _ => {
+                            return Err(format!(
+                                "line {}: expected default or emacs, got `{}`",
+                                line_number, value
+                            ))
+                        }
+                    };
+                }
+                "bind" => {
+                    let (keys, command) = parse_binding(value)
+                        .map_err(|error| format!("line {}: {}", line_number, error))?;
+                    config.keymap.bind(keys, command);
+                }
+                "unbind" => {
+                    let keys = keymap::parse_keys(value)
+                        .map_err(|error| format!("line {}: {}", line_number, error))?;
+                    config.keymap.unbind(&keys);
+                }
+                _ => return Err(format!("line {}: unknown option `{}`", line_number, option)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn parse_bool(value: &str, line_number: usize) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err(format!(
+            "line {}: expected true or false, got `{}`",
+            line_number, value
+        )),
+    }
+}
+
+/// Parses `KEYS COMMAND`, as in `Ctrl-K Ctrl-C save`.
+fn parse_binding(value: &str) -> Result<(Vec<Key>, Command), String> {
+    let mut parts = value.rsplitn(2, char::is_whitespace);
+    let (name, keys) = match (parts.next(), parts.next()) {
+        (Some(name), Some(keys)) => (name, keys),
+        _ => return Err("expected `keys command`".to_string()),
+    };
+    let command = Command::from_name(name).ok_or_else(|| format!("unknown command `{}`", name))?;
+    Ok((keymap::parse_keys(keys)?, command))
+}
+
+/// Expands a leading `~/` to the home directory.
+fn expand_home(value: &str) -> PathBuf {
+    match (value.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(value),
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse("# comment\n\nauto_pairs = off\n").unwrap();
+        assert!(!config.auto_pairs);
+        assert!(!config.backup.enabled);
+        assert!(!config.modal);
+        let config = Config::parse("backup_dir = /tmp/backups\nbackup_keep = 3").unwrap();
+        assert!(config.backup.enabled);
+        assert_eq!(config.backup.dir, Some(PathBuf::from("/tmp/backups")));
+        assert_eq!(config.backup.keep, 3);
+        assert!(Config::parse("auto_pairs = maybe").is_err());
+        assert!(Config::parse("colour = blue").is_err());
+
+        let config = Config::parse("bind = Ctrl-K Ctrl-C save\nunbind = Ctrl-S").unwrap();
+        assert_eq!(config.keymap.hint(Command::Save), "Ctrl-K Ctrl-C");
+        assert!(Config::parse("bind = Ctrl-K").is_err());
+        assert!(Config::parse("bind = Ctrl-K frobnicate").is_err());
+        let config = Config::parse("keymap = emacs\nbind = Ctrl-X Ctrl-F find").unwrap();
+        assert_eq!(config.keymap.hint(Command::Yank), "Ctrl-Y");
+        assert_eq!(config.keymap.hint(Command::Find), "Ctrl-S");
+        assert!(Config::parse("keymap = ed").is_err());
+    }
+}
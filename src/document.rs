@@ -1,9 +1,76 @@
+use crate::encoding;
+use crate::storage;
+use crate::swap;
+use crate::Backup;
+use crate::Encoding;
 use crate::FileType;
+use crate::IndentStyle;
+use crate::Indentation;
 use crate::Position;
 use crate::Row;
 use crate::SearchDirection;
 use std::fs;
-use std::io::{Error, Write};
+use std::io::Error;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use unicode_segmentation::UnicodeSegmentation;
+
+const INDENT_SAMPLE_ROWS: usize = 1000;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl Default for LineEnding {
+    fn default() -> Self {
+        Self::Lf
+    }
+}
+
+impl LineEnding {
+    /// The style used by the majority of the lines in `contents`.
+    fn detect(contents: &str) -> Self {
+        let crlf = contents.matches("\r\n").count();
+        let lf = contents.matches('\n').count().saturating_sub(crlf);
+        if crlf > lf {
+            Self::CrLf
+        } else {
+            Self::Lf
+        }
+    }
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Lf => "LF",
+            Self::CrLf => "CRLF",
+        }
+    }
+}
+
+/// What a file looked like on disk, to notice when something else changes it.
+#[derive(PartialEq, Clone, Copy)]
+struct DiskStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl DiskStamp {
+    fn read(file_name: &str) -> Option<Self> {
+        let metadata = fs::metadata(file_name).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+}
 
 #[derive(Default)]
 pub struct Document {
@@ -11,26 +78,171 @@ pub struct Document {
     pub file_name: Option<String>,
     dirty: bool,
     file_type: FileType,
+    indentation: Indentation,
+    read_only: bool,
+    line_ending: LineEnding,
+    missing_final_newline: bool,
+    encoding: Encoding,
+    /// The file as it was when last read or written.
+    disk_stamp: Option<DiskStamp>,
+    /// A change on disk the user chose not to reload.
+    ignored_stamp: Option<DiskStamp>,
 }
 
 impl Document {
+    /// An empty document for a file that does not exist yet.
+    pub fn new(filename: &str) -> Self {
+        let file_type = FileType::from(filename);
+        Self {
+            file_name: Some(filename.to_string()),
+            indentation: file_type.indentation(),
+            file_type,
+            ..Self::default()
+        }
+    }
+    /// A read-only document showing `lines`, such as a diff.
+    pub fn scratch(lines: &[String]) -> Self {
+        Self {
+            rows: lines.iter().map(|line| Row::from(line.as_str())).collect(),
+            read_only: true,
+            ..Self::default()
+        }
+    }
+    /// Opens `filename`, decoding it from whatever encoding it is in. Binary
+    /// files are opened as a read-only hex dump.
     pub fn open(filename: &str) -> Result<Self, std::io::Error> {
-        let contents = fs::read_to_string(filename)?;
+        let bytes = fs::read(filename)?;
+        let mut document = Self::from_bytes(filename, &bytes);
+        document.disk_stamp = DiskStamp::read(filename);
+        document.read_only |= !storage::is_writable(Path::new(filename));
+        Ok(document)
+    }
+    /// An unnamed document holding `bytes`, such as the text piped to hecto.
+    pub fn from_stdin(bytes: &[u8]) -> Self {
+        let mut document = Self::from_bytes("", bytes);
+        document.file_name = None;
+        document
+    }
+    fn from_bytes(filename: &str, bytes: &[u8]) -> Self {
+        if let Some((encoding, contents)) = Encoding::decode(bytes) {
+            let mut document = Self::from_contents(filename, &contents);
+            document.encoding = encoding;
+            // Saving would replace the invalid bytes for good.
+            document.read_only = encoding == Encoding::Utf8Lossy;
+            return document;
+        }
+        Self {
+            rows: encoding::hex_dump(bytes)
+                .iter()
+                .map(|line| Row::from(line.as_str()))
+                .collect(),
+            file_name: Some(filename.to_string()),
+            read_only: true,
+            encoding: Encoding::Binary,
+            ..Self::default()
+        }
+    }
+    /// Splits `contents` into rows, remembering the line ending style and
+    /// whether the last line ended in a newline so `to_bytes` can restore them.
+    fn from_contents(filename: &str, contents: &str) -> Self {
         let file_type = FileType::from(filename);
+        let line_ending = LineEnding::detect(contents);
+        let missing_final_newline = !contents.is_empty() && !contents.ends_with('\n');
         let mut rows = Vec::new();
-        for value in contents.lines() {
-            rows.push(Row::from(value));
+        let mut values = contents.split_terminator('\n').peekable();
+        while let Some(value) = values.next() {
+            let (text, ending) = match value.strip_suffix('\r') {
+                Some(text) => (text, LineEnding::CrLf),
+                None => (value, LineEnding::Lf),
+            };
+            let mut row = Row::from(text);
+            // Rows that differ from the majority keep their own ending. An
+            // unterminated last line has none to keep.
+            let unterminated = missing_final_newline && values.peek().is_none();
+            if ending != line_ending && !unterminated {
+                row.set_line_ending(Some(ending));
+            }
+            rows.push(row);
         }
-        Ok(Self {
+        let indentation = Indentation::detect(
+            rows.iter().take(INDENT_SAMPLE_ROWS).map(Row::as_str),
+            file_type.indentation(),
+        );
+        Self {
             rows,
             file_name: Some(filename.to_string()),
             dirty: false,
             file_type,
-        })
+            indentation,
+            read_only: false,
+            line_ending,
+            missing_final_newline,
+            encoding: Encoding::Utf8,
+            disk_stamp: None,
+            ignored_stamp: None,
+        }
+    }
+    /// The rows joined by newlines, as kept in the swap file.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for row in &self.rows {
+            text.push_str(row.as_str());
+            text.push('\n');
+        }
+        text
+    }
+    /// Replaces all rows with `text`, for example from a recovered swap file.
+    pub fn replace_text(&mut self, text: &str) {
+        self.rows = text.split_terminator('\n').map(Row::from).collect();
+        self.dirty = true;
+    }
+    pub fn swap_path(&self) -> Option<PathBuf> {
+        self.file_name.as_deref().map(swap::path)
+    }
+    /// Encodes the rows in `range` as they are written to disk.
+    fn to_bytes(&self, range: Range<usize>) -> Result<Vec<u8>, Error> {
+        let mut contents = String::new();
+        for (index, row) in self
+            .rows
+            .iter()
+            .enumerate()
+            .take(range.end)
+            .skip(range.start)
+        {
+            contents.push_str(row.as_str());
+            if !self.missing_final_newline || index.saturating_add(1) < self.rows.len() {
+                contents.push_str(row.line_ending().unwrap_or(self.line_ending).as_str());
+            }
+        }
+        self.encoding.encode(&contents)
     }
     pub fn file_type(&self) -> String {
         self.file_type.name()
     }
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+    /// Switches every row, including any with an ending of its own, to
+    /// `line_ending`.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        let mixed = self.has_mixed_line_endings();
+        if line_ending != self.line_ending || mixed {
+            self.line_ending = line_ending;
+            for row in &mut self.rows {
+                row.set_line_ending(None);
+            }
+            self.dirty = true;
+        }
+    }
+    pub fn has_mixed_line_endings(&self) -> bool {
+        self.rows.iter().any(|row| row.line_ending().is_some())
+    }
+    pub fn indentation(&self) -> Indentation {
+        self.indentation
+    }
     pub fn row(&self, index: usize) -> Option<&Row> {
         self.rows.get(index)
     }
@@ -40,37 +252,333 @@ impl Document {
     pub fn len(&self) -> usize {
         self.rows.len()
     }
-    fn insert_newline(&mut self, at: &Position) {
+    fn insert_newline(&mut self, at: &Position) -> Position {
         if at.y > self.rows.len() {
-            return;
+            return at.clone();
         }
+        #[allow(clippy::integer_arithmetic)]
+        let next = Position { x: 0, y: at.y + 1 };
         if at.y == self.rows.len() {
             self.rows.push(Row::default());
-            return;
+            return next;
         }
         #[allow(clippy::indexing_slicing)]
         let current_row = &mut self.rows[at.y];
-        let new_row = current_row.split(at.x);
+        let mut indent = current_row.leading_whitespace().to_string();
+        if at.x <= indent.len() {
+            // Splitting inside the indentation moves the whole line down,
+            // indentation and all, and leaves an empty line behind.
+            let new_row = current_row.split(0);
+            #[allow(clippy::integer_arithmetic)]
+            self.rows.insert(at.y + 1, new_row);
+            return Position { x: at.x, y: next.y };
+        }
+        let mut new_row = current_row.split(at.x);
+        // The new indentation replaces any whitespace around the split.
+        current_row.trim_end();
+        new_row.trim_start();
+        let opens_block = current_row
+            .as_str()
+            .trim_end()
+            .chars()
+            .last()
+            .map_or(false, |c| self.file_type.indent_openers().contains(&c));
+        let closes_block = new_row
+            .as_str()
+            .chars()
+            .next()
+            .map_or(false, |c| self.file_type.indent_closers().contains(&c));
+        if opens_block {
+            let outer_indent = indent.clone();
+            indent.push_str(&self.indentation.unit());
+            if closes_block {
+                let mut closing_row = new_row;
+                closing_row.insert_str(0, &outer_indent);
+                new_row = Row::default();
+                #[allow(clippy::integer_arithmetic)]
+                self.rows.insert(at.y + 1, closing_row);
+            }
+        }
+        new_row.insert_str(0, &indent);
         #[allow(clippy::integer_arithmetic)]
         self.rows.insert(at.y + 1, new_row);
+        Position {
+            x: indent.len(),
+            y: next.y,
+        }
     }
-    pub fn insert(&mut self, at: &Position, c: char) {
+    /// Removes one level of indentation in front of `at` if nothing but
+    /// whitespace precedes it, so a closing bracket lines up with its opener.
+    fn dedent(&mut self, at: &Position) -> usize {
+        let row = if let Some(row) = self.rows.get_mut(at.y) {
+            row
+        } else {
+            return at.x;
+        };
+        if at.x == 0 || row.leading_whitespace().len() < at.x {
+            return at.x;
+        }
+        #[allow(clippy::indexing_slicing)]
+        let width = if row.as_str()[..at.x].ends_with('\t') {
+            1
+        } else {
+            row.as_str()[..at.x]
+                .chars()
+                .rev()
+                .take(self.indentation.width)
+                .take_while(|c| *c == ' ')
+                .count()
+        };
+        let x = at.x.saturating_sub(width);
+        for _ in 0..width {
+            row.delete(x);
+        }
+        x
+    }
+    /// Inserts `c` at the given position and returns where the cursor should
+    /// go afterwards, which differs from one column to the right whenever
+    /// auto-indentation adds or removes whitespace.
+    pub fn insert(&mut self, at: &Position, c: char) -> Position {
         if at.y > self.rows.len() {
-            return;
+            return at.clone();
         }
         self.dirty = true;
         if c == '\n' {
-            self.insert_newline(at);
-        } else if at.y == self.rows.len() {
+            let position = self.insert_newline(at);
+            self.unhighlight_rows(at.y);
+            return position;
+        }
+        let mut x = at.x;
+        if at.y == self.rows.len() {
             let mut row = Row::default();
             row.insert(0, c);
             self.rows.push(row);
         } else {
+            if self.file_type.indent_closers().contains(&c) {
+                x = self.dedent(at);
+            }
             #[allow(clippy::indexing_slicing)]
             let row = &mut self.rows[at.y];
-            row.insert(at.x, c);
+            row.insert(x, c);
+        }
+        self.unhighlight_rows(at.y);
+        Position {
+            x: x.saturating_add(1),
+            y: at.y,
+        }
+    }
+
+    /// Inserts one level of indentation at `at`: a tab, or enough spaces to
+    /// reach the next indentation stop.
+    pub fn insert_tab(&mut self, at: &Position) -> Position {
+        let width = self.indentation.width.max(1);
+        let column = self
+            .rows
+            .get(at.y)
+            .map_or(0, |row| row.render_x(at.x, width));
+        let text = match self.indentation.style {
+            IndentStyle::Tabs => String::from("\t"),
+            #[allow(clippy::integer_arithmetic)]
+            IndentStyle::Spaces => " ".repeat(width - column % width),
+        };
+        self.insert_str(at, &text)
+    }
+    /// Adds one level of indentation to the start of row `y`, unless it is
+    /// blank. Returns the number of characters added.
+    pub fn indent(&mut self, y: usize) -> usize {
+        let unit = self.indentation.unit();
+        if let Some(row) = self.rows.get_mut(y) {
+            if row.as_str().trim().is_empty() {
+                return 0;
+            }
+            row.insert_str(0, &unit);
+            self.dirty = true;
+            self.unhighlight_rows(y);
+            return unit.len();
+        }
+        0
+    }
+    /// Removes up to one level of indentation from the start of row `y` and
+    /// returns the number of characters removed.
+    pub fn outdent(&mut self, y: usize) -> usize {
+        let width = self.indentation.width;
+        let row = if let Some(row) = self.rows.get_mut(y) {
+            row
+        } else {
+            return 0;
+        };
+        let count = if row.as_str().starts_with('\t') {
+            1
+        } else {
+            row.as_str()
+                .chars()
+                .take(width)
+                .take_while(|c| *c == ' ')
+                .count()
+        };
+        for _ in 0..count {
+            row.delete(0);
+        }
+        if count > 0 {
+            self.dirty = true;
+            self.unhighlight_rows(y);
+        }
+        count
+    }
+    /// Inserts `text` verbatim, without any auto-indentation, and returns the
+    /// position right after it.
+    #[allow(clippy::indexing_slicing, clippy::integer_arithmetic)]
+    pub fn insert_str(&mut self, at: &Position, text: &str) -> Position {
+        if at.y > self.rows.len() || text.is_empty() {
+            return at.clone();
+        }
+        self.dirty = true;
+        if at.y == self.rows.len() {
+            self.rows.push(Row::default());
         }
+        let tail = self.rows[at.y].split(at.x);
+        let mut lines = text.split('\n');
+        let mut y = at.y;
+        if let Some(first) = lines.next() {
+            let row = &mut self.rows[y];
+            row.insert_str(row.len(), first);
+        }
+        for line in lines {
+            y += 1;
+            self.rows.insert(y, Row::from(line));
+        }
+        let row = &mut self.rows[y];
+        let x = row.len();
+        row.append(&tail);
         self.unhighlight_rows(at.y);
+        Position { x, y }
+    }
+    /// Deletes everything from `start` up to, but not including, `end`.
+    #[allow(clippy::indexing_slicing, clippy::integer_arithmetic)]
+    pub fn delete_range(&mut self, start: &Position, end: &Position) {
+        if start.y >= self.rows.len() {
+            return;
+        }
+        self.dirty = true;
+        let (end_x, end_y) = if end.y < self.rows.len() {
+            (end.x, end.y)
+        } else {
+            let last = self.rows.len() - 1;
+            (self.rows[last].len(), last)
+        };
+        let tail = self.rows[end_y].split(end_x);
+        self.rows[start.y].split(start.x);
+        self.rows.drain(start.y + 1..=end_y);
+        self.rows[start.y].append(&tail);
+        self.unhighlight_rows(start.y);
+    }
+    /// The text from `start` up to, but not including, `end`.
+    pub fn text_between(&self, start: &Position, end: &Position) -> String {
+        let last = end.y.min(self.rows.len().saturating_sub(1));
+        let mut lines = Vec::new();
+        for (y, row) in self
+            .rows
+            .iter()
+            .enumerate()
+            .take(last.saturating_add(1))
+            .skip(start.y)
+        {
+            let from = if y == start.y { start.x } else { 0 };
+            let to = if y == end.y { end.x } else { row.len() };
+            let line: String = row
+                .as_str()
+                .graphemes(true)
+                .skip(from)
+                .take(to.saturating_sub(from))
+                .collect();
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+    /// Deletes the rows `first` to `last`, leaving an empty document with
+    /// a single empty row rather than none.
+    #[allow(clippy::integer_arithmetic)]
+    pub fn delete_lines(&mut self, first: usize, last: usize) {
+        let len = self.rows.len();
+        if first >= len {
+            return;
+        }
+        let last = last.min(len - 1);
+        let row_len = |y: usize| self.rows.get(y).map_or(0, Row::len);
+        let (start, end) = if last + 1 < len {
+            (Position { x: 0, y: first }, Position { x: 0, y: last + 1 })
+        } else if first > 0 {
+            (
+                Position {
+                    x: row_len(first - 1),
+                    y: first - 1,
+                },
+                Position {
+                    x: row_len(last),
+                    y: last,
+                },
+            )
+        } else {
+            (
+                Position { x: 0, y: 0 },
+                Position {
+                    x: row_len(last),
+                    y: last,
+                },
+            )
+        };
+        self.delete_range(&start, &end);
+    }
+    /// The position after the end of the next word, moving on to the start of
+    /// the next row at the end of a row.
+    pub fn next_word(&self, at: &Position) -> Position {
+        match self.rows.get(at.y) {
+            Some(row) if at.x < row.len() => Position {
+                x: row.next_word_end(at.x),
+                y: at.y,
+            },
+            _ if at.y < self.rows.len() => Position {
+                x: 0,
+                y: at.y.saturating_add(1),
+            },
+            _ => at.clone(),
+        }
+    }
+    /// The start of the previous word, moving on to the end of the previous
+    /// row at the start of a row.
+    pub fn previous_word(&self, at: &Position) -> Position {
+        match self.rows.get(at.y) {
+            Some(row) if at.x > 0 => Position {
+                x: row.previous_word_start(at.x),
+                y: at.y,
+            },
+            _ if at.y > 0 => {
+                let y = at.y.saturating_sub(1);
+                Position {
+                    x: self.rows.get(y).map_or(0, Row::len),
+                    y,
+                }
+            }
+            _ => at.clone(),
+        }
+    }
+    /// Deletes from the start of the previous word up to `at` and returns the
+    /// new cursor position.
+    pub fn delete_word_backward(&mut self, at: &Position) -> Position {
+        let start = self.previous_word(at);
+        if start != *at {
+            self.delete_range(&start, at);
+        }
+        start
+    }
+    pub fn delete_word_forward(&mut self, at: &Position) {
+        let end = self.next_word(at);
+        if end != *at {
+            self.delete_range(at, &end);
+        }
+    }
+    pub fn pairs(&self) -> &Vec<(char, char)> {
+        self.file_type.pairs()
     }
 
     fn unhighlight_rows(&mut self, start: usize) {
@@ -96,21 +604,79 @@ impl Document {
         }
         self.unhighlight_rows(at.y);
     }
-    pub fn save(&mut self) -> Result<(), Error> {
+    /// Writes the document to its file, first keeping the previous version
+    /// as `backup` says.
+    pub fn save(&mut self, backup: &Backup) -> Result<(), Error> {
         if let Some(file_name) = &self.file_name {
-            let mut file = fs::File::create(file_name)?;
+            let contents = self.contents()?;
+            backup.save(Path::new(file_name)).map_err(|error| {
+                Error::new(error.kind(), format!("could not make a backup: {}", error))
+            })?;
+            storage::write_file(Path::new(file_name), &contents)?;
+            swap::remove(&swap::path(file_name));
+            self.disk_stamp = DiskStamp::read(file_name);
+            self.ignored_stamp = None;
             self.file_type = FileType::from(file_name);
-            for row in &mut self.rows {
-                file.write_all(row.as_bytes())?;
-                file.write_all(b"\n")?;
+            if self.encoding == Encoding::Utf8Lossy {
+                self.encoding = Encoding::Utf8;
             }
             self.dirty = false;
         }
         Ok(())
     }
+    /// The whole document as it would be written to its file.
+    pub fn contents(&self) -> Result<Vec<u8>, Error> {
+        self.to_bytes(0..self.rows.len())
+    }
+    /// Writes the rows in `range` to `path`, in the document's encoding and
+    /// line endings.
+    pub fn write_rows(&self, path: &Path, range: Range<usize>) -> Result<(), Error> {
+        storage::write_file(path, &self.to_bytes(range)?)
+    }
+    /// Gives the document a new file name, for example after the file was
+    /// renamed, and picks the file type to match.
+    pub fn set_file_name(&mut self, file_name: &str) {
+        self.file_name = Some(file_name.to_string());
+        self.file_type = FileType::from(file_name);
+    }
+    /// Whether the file was changed on disk since it was last read or
+    /// written.
+    pub fn changed_on_disk(&self) -> bool {
+        let current = self.read_disk_stamp();
+        current.is_some() && current != self.disk_stamp
+    }
+    /// Whether the file changed on disk in a way the user has not yet been
+    /// asked about.
+    pub fn has_new_disk_change(&self) -> bool {
+        let current = self.read_disk_stamp();
+        current.is_some() && current != self.disk_stamp && current != self.ignored_stamp
+    }
+    /// Stops `has_new_disk_change` from reporting the current change.
+    pub fn ignore_disk_change(&mut self) {
+        self.ignored_stamp = self.read_disk_stamp();
+    }
+    fn read_disk_stamp(&self) -> Option<DiskStamp> {
+        self.file_name.as_deref().and_then(DiskStamp::read)
+    }
+    /// Reads the file again, dropping any unsaved changes.
+    pub fn reload(&mut self) -> Result<(), Error> {
+        if let Some(file_name) = &self.file_name {
+            let mut document = Self::open(file_name)?;
+            document.read_only |= self.read_only;
+            swap::remove(&swap::path(file_name));
+            *self = document;
+        }
+        Ok(())
+    }
     pub fn is_dirty(&self) -> bool {
         self.dirty
     }
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
     #[allow(clippy::indexing_slicing)]
     pub fn find(&self, query: &str, at: &Position, direction: SearchDirection) -> Option<Position> {
         if at.y >= self.rows.len() {
@@ -168,3 +734,153 @@ impl Document {
         }
     }
 }
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+    use crate::tempdir::TempDir;
+
+    fn rust_document(lines: &[&str]) -> Document {
+        Document {
+            rows: lines.iter().map(|line| Row::from(*line)).collect(),
+            file_name: None,
+            dirty: false,
+            file_type: FileType::from("test.rs"),
+            indentation: Indentation::default(),
+            read_only: false,
+            line_ending: LineEnding::Lf,
+            missing_final_newline: false,
+            encoding: Encoding::Utf8,
+            disk_stamp: None,
+            ignored_stamp: None,
+        }
+    }
+
+    #[test]
+    fn test_newline_keeps_indentation() {
+        let mut doc = rust_document(&["    let a = 1;"]);
+        let position = doc.insert(&Position { x: 14, y: 0 }, '\n');
+        assert_eq!((position.x, position.y), (4, 1));
+        assert_eq!(doc.row(1).map(Row::as_str), Some("    "));
+    }
+
+    #[test]
+    fn test_newline_trims_whitespace_around_split() {
+        let mut doc = rust_document(&["    let a =   1;  "]);
+        let position = doc.insert(&Position { x: 12, y: 0 }, '\n');
+        assert_eq!((position.x, position.y), (4, 1));
+        assert_eq!(doc.row(0).map(Row::as_str), Some("    let a ="));
+        assert_eq!(doc.row(1).map(Row::as_str), Some("    1;  "));
+    }
+
+    #[test]
+    fn test_newline_inside_indentation() {
+        let mut doc = rust_document(&["    foo", "    bar"]);
+        let position = doc.insert(&Position { x: 0, y: 0 }, '\n');
+        assert_eq!((position.x, position.y), (0, 1));
+        assert_eq!(doc.row(0).map(Row::as_str), Some(""));
+        assert_eq!(doc.row(1).map(Row::as_str), Some("    foo"));
+        let position = doc.insert(&Position { x: 2, y: 2 }, '\n');
+        assert_eq!((position.x, position.y), (2, 3));
+        assert_eq!(doc.row(2).map(Row::as_str), Some(""));
+        assert_eq!(doc.row(3).map(Row::as_str), Some("    bar"));
+    }
+
+    #[test]
+    fn test_newline_indents_after_opener() {
+        let mut doc = rust_document(&["fn main() {}"]);
+        let position = doc.insert(&Position { x: 11, y: 0 }, '\n');
+        assert_eq!((position.x, position.y), (4, 1));
+        assert_eq!(doc.row(1).map(Row::as_str), Some("    "));
+        assert_eq!(doc.row(2).map(Row::as_str), Some("}"));
+    }
+
+    #[test]
+    fn test_tab_stops_follow_render_columns() {
+        let mut doc = rust_document(&["\tx", "日本x"]);
+        let position = doc.insert_tab(&Position { x: 2, y: 0 });
+        assert_eq!(doc.row(0).map(Row::as_str), Some("\tx   "));
+        assert_eq!(position.x, 5);
+        doc.insert_tab(&Position { x: 2, y: 1 });
+        assert_eq!(doc.row(1).map(Row::as_str), Some("日本    x"));
+    }
+
+    #[test]
+    fn test_closer_dedents() {
+        let mut doc = rust_document(&["        "]);
+        let position = doc.insert(&Position { x: 8, y: 0 }, '}');
+        assert_eq!(position.x, 5);
+        assert_eq!(doc.row(0).map(Row::as_str), Some("    }"));
+    }
+
+    #[test]
+    fn test_insert_and_delete_range() {
+        let mut doc = rust_document(&["let a = (1);", "b"]);
+        let end = doc.insert_str(&Position { x: 10, y: 0 }, "\n    2,\n");
+        assert_eq!((end.x, end.y), (0, 2));
+        assert_eq!(doc.row(1).map(Row::as_str), Some("    2,"));
+        assert_eq!(doc.row(2).map(Row::as_str), Some(");"));
+        doc.delete_range(&Position { x: 9, y: 0 }, &Position { x: 0, y: 2 });
+        assert_eq!(doc.row(0).map(Row::as_str), Some("let a = ();"));
+        assert_eq!(doc.len(), 2);
+    }
+
+    #[test]
+    fn test_line_endings_round_trip() {
+        for contents in &["a\r\nb\r\n", "a\nb", "a\n\n", ""] {
+            let doc = Document::from_contents("test.txt", contents);
+            assert_eq!(doc.to_bytes(0..doc.len()).unwrap(), contents.as_bytes());
+        }
+        let doc = Document::from_contents("test.txt", "a\r\nb\r\n");
+        assert_eq!(doc.line_ending(), LineEnding::CrLf);
+        assert_eq!(doc.row(0).map(Row::as_str), Some("a"));
+    }
+
+    #[test]
+    fn test_disk_stamps() {
+        let dir = TempDir::new("stamp");
+        let file_name = dir.write("notes.txt", "one\n");
+        let mut doc = Document::open(&file_name).unwrap();
+        assert!(!doc.changed_on_disk());
+        fs::write(&file_name, "one\ntwo\n").unwrap();
+        assert_eq!(
+            (doc.changed_on_disk(), doc.has_new_disk_change()),
+            (true, true)
+        );
+        doc.ignore_disk_change();
+        assert_eq!(
+            (doc.changed_on_disk(), doc.has_new_disk_change()),
+            (true, false)
+        );
+        fs::write(&file_name, "one\ntwo\nthree\n").unwrap();
+        assert!(doc.has_new_disk_change());
+        doc.save(&Backup::default()).unwrap();
+        assert!(!doc.changed_on_disk());
+    }
+
+    #[test]
+    fn test_mixed_line_endings_round_trip() {
+        let contents = "a\r\nb\nc\r\nd";
+        let mut doc = Document::from_contents("test.txt", contents);
+        assert!(doc.has_mixed_line_endings());
+        assert_eq!(doc.to_bytes(0..doc.len()).unwrap(), contents.as_bytes());
+
+        // A split row keeps its ending on the part after the break, and a
+        // joined row takes the ending of the row joined onto it.
+        doc.insert(&Position { x: 1, y: 1 }, '!');
+        doc.insert(&Position { x: 0, y: 1 }, '\n');
+        doc.delete(&Position { x: 2, y: 2 });
+        assert_eq!(doc.to_bytes(0..doc.len()).unwrap(), b"a\r\n\r\nb!c\r\nd");
+
+        doc.set_line_ending(LineEnding::Lf);
+        assert!(!doc.has_mixed_line_endings());
+        assert_eq!(doc.to_bytes(0..doc.len()).unwrap(), b"a\n\nb!c\nd");
+
+        // The last line of a file without a final newline has no ending to
+        // tell it apart.
+        let mut doc = Document::from_contents("test.txt", "a\r\nb\r\nc");
+        assert!(!doc.has_mixed_line_endings());
+        doc.set_line_ending(LineEnding::Lf);
+        assert_eq!(doc.to_bytes(0..doc.len()).unwrap(), b"a\nb\nc");
+    }
+}
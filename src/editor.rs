@@ -1,16 +1,39 @@
+use crate::fuzzy;
+use crate::keymap::{format_keys, Binding, Command};
+use crate::killring::KillRing;
+use crate::pipe;
+use crate::swap;
+use crate::vim::{self, Action, Mode, Motion, Operator, Register, Target, Vim};
+use crate::watch::Watcher;
+use crate::Args;
+use crate::Backend;
+use crate::Config;
 use crate::Document;
+use crate::Encoding;
+use crate::IndentStyle;
+use crate::Key;
+use crate::LineEnding;
 use crate::Row;
+use crate::Size;
 use crate::Terminal;
-use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::mem;
+use std::ops::Range;
+use std::path::Path;
 use std::time::Duration;
 use std::time::Instant;
 use termion::color;
-use termion::event::Key;
+use unicode_segmentation::UnicodeSegmentation;
 
 const STATUS_FG_COLOR: color::Rgb = color::Rgb(63, 63, 63);
 const STATUS_BG_COLOR: color::Rgb = color::Rgb(239, 239, 239);
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const QUIT_TIMES: u8 = 3;
+const SWAP_INTERVAL: Duration = Duration::from_secs(2);
+const MESSAGE_DURATION: Duration = Duration::from_secs(5);
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+const WHEEL_LINES: usize = 3;
 
 #[derive(PartialEq, Copy, Clone)]
 pub enum SearchDirection {
@@ -18,12 +41,21 @@ pub enum SearchDirection {
     Backward,
 }
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, PartialEq, Debug)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
 }
 
+/// A document together with where the cursor was in it, for switching
+/// between the files given on the command line.
+#[derive(Default)]
+struct Buffer {
+    document: Document,
+    cursor_position: Position,
+    offset: Position,
+}
+
 struct StatusMessage {
     text: String,
     time: Instant,
@@ -35,103 +67,620 @@ impl StatusMessage {
             text: message,
         }
     }
+    fn is_shown(&self) -> bool {
+        !self.text.is_empty() && self.time.elapsed() < MESSAGE_DURATION
+    }
 }
 
 pub struct Editor {
     should_quit: bool,
-    terminal: Terminal,
+    terminal: Box<dyn Backend>,
     cursor_position: Position,
     offset: Position,
     document: Document,
     status_message: StatusMessage,
     quit_times: u8,
     highlighted_word: Option<String>,
+    selection: Option<Position>,
+    config: Config,
+    buffers: Vec<Buffer>,
+    buffer_index: usize,
+    swap_written: Option<Instant>,
+    /// Whether keys were processed since the swap file was last written.
+    swap_pending: bool,
+    watcher: Watcher,
+    /// Whether a buffer goes to stdout on exit, which makes changes to the
+    /// buffer read from stdin worth keeping.
+    to_stdout: bool,
+    /// The index of the buffer read from stdin, if there is one.
+    stdin_buffer: Option<usize>,
+    /// When and where the last single click was, to spot double clicks.
+    last_click: Option<(Instant, Position)>,
+    /// The keys of a chord typed so far.
+    pending_keys: Vec<Key>,
+    /// The state of modal editing, if it is on.
+    vim: Option<Vim>,
+    kill_ring: KillRing,
+    /// Whether the selection was started with `set-mark`, which makes
+    /// plain motions extend it.
+    mark_set: bool,
+    /// The last command run, for kills in a row and `yank-pop`.
+    last_command: Option<Command>,
+    /// Where the last yank was inserted.
+    yanked: Option<(Position, Position)>,
 }
 
 impl Editor {
-    pub fn run(&mut self) {
+    pub fn run(&mut self) -> Result<(), std::io::Error> {
+        self.check_swaps()?;
         loop {
-            if let Err(error) = self.refresh_screen() {
-                die(error);
-            }
             if self.should_quit {
-                break;
+                self.remove_swaps();
+                return Ok(());
             }
-            if let Err(error) = self.process_keypress() {
-                die(error);
+            self.refresh_screen()?;
+            self.wait_and_process()?;
+        }
+    }
+    /// Processes the next key, if one comes before `idle_timeout`, and then
+    /// does what falls due: writing the swap file and reacting to changed
+    /// files.
+    fn wait_and_process(&mut self) -> Result<(), std::io::Error> {
+        if let Some(key) = self.terminal.poll_key(self.idle_timeout())? {
+            self.process_keypress(key)?;
+            self.swap_pending = true;
+        }
+        if self.swap_pending {
+            self.update_swap();
+        }
+        if self.watcher.changed() {
+            self.check_disk_change()?;
+        }
+        Ok(())
+    }
+    /// How long to wait for a key before something needs doing anyway: the
+    /// status message going away, a swap file write falling due or the
+    /// next check for changed files.
+    fn idle_timeout(&self) -> Duration {
+        let mut deadline = self.watcher.next_check();
+        if self.status_message.is_shown() {
+            deadline = deadline.min(self.status_message.time + MESSAGE_DURATION);
+        }
+        if self.swap_pending {
+            if let Some(written) = self.swap_written {
+                deadline = deadline.min(written + SWAP_INTERVAL);
             }
         }
+        deadline.saturating_duration_since(Instant::now())
     }
-    pub fn default() -> Self {
-        let args: Vec<String> = env::args().collect();
-        let mut initial_status =
-            String::from("HELP: Ctrl-F = find | Ctrl-S = save | Ctrl-Q = quit");
-
-        let document = if let Some(file_name) = args.get(1) {
-            let doc = Document::open(file_name);
-            if let Ok(doc) = doc {
-                doc
+    pub fn new(args: Args) -> Result<Self, String> {
+        let (config, config_error) = match Config::load() {
+            Ok(config) => (config, None),
+            Err(error) => (Config::default(), Some(error)),
+        };
+        let terminal = Terminal::default()
+            .map_err(|error| format!("Failed to initialize terminal: {}", error))?;
+        let mut editor = Self::with_backend(args, config, Box::new(terminal))?;
+        if let Some(error) = config_error {
+            editor.status_message =
+                StatusMessage::from(format!("ERR: Could not load config: {}", error));
+        }
+        Ok(editor)
+    }
+    /// An editor for the files in `args` that draws on and reads keys from
+    /// `terminal`.
+    pub fn with_backend(
+        args: Args,
+        config: Config,
+        terminal: Box<dyn Backend>,
+    ) -> Result<Self, String> {
+        let keymap = &config.keymap;
+        let mut initial_status = format!(
+            "HELP: {} = commands | {} = find | {} = save | {} = quit | {} = all keys",
+            keymap.hint(Command::Palette),
+            keymap.hint(Command::Find),
+            keymap.hint(Command::Save),
+            keymap.hint(Command::Quit),
+            keymap.hint(Command::Help),
+        );
+        let mut buffers = Vec::new();
+        let mut watcher = Watcher::default();
+        let mut stdin_buffer = None;
+        for file in &args.files {
+            let mut document = if file.path == "-" {
+                stdin_buffer = Some(buffers.len());
+                let bytes =
+                    pipe::read_stdin().map_err(|error| format!("cannot read stdin: {}", error))?;
+                Document::from_stdin(&bytes)
             } else {
-                initial_status = format!("ERR: Could not open file: {}", file_name);
-                Document::default()
+                watcher.watch(Path::new(&file.path));
+                match Document::open(&file.path) {
+                    Ok(document) => document,
+                    Err(error) if error.kind() == ErrorKind::NotFound => Document::new(&file.path),
+                    Err(error) => return Err(format!("{}: {}", file.path, error)),
+                }
+            };
+            if args.read_only {
+                document.set_read_only(true);
             }
-        } else {
-            Document::default()
-        };
+            let cursor_position = start_position(&document, file.line, file.column);
+            buffers.push(Buffer {
+                document,
+                cursor_position,
+                offset: Position::default(),
+            });
+        }
+        if buffers.is_empty() {
+            buffers.push(Buffer::default());
+        }
+        for buffer in &mut buffers {
+            buffer.offset = centered_offset(terminal.size(), buffer.cursor_position.y);
+        }
+        let first = buffers.get_mut(0).map(mem::take).unwrap_or_default();
+        if first.document.encoding() == Encoding::Binary {
+            initial_status = String::from("Binary file, shown as a read-only hex dump.");
+        } else if first.document.encoding() == Encoding::Utf8Lossy {
+            initial_status = format!(
+                "Invalid UTF-8 shown as \u{fffd}, opened read-only. {} saves a copy.",
+                keymap.hint(Command::Save)
+            );
+        } else if first.document.is_read_only() && !args.read_only {
+            initial_status = format!(
+                "File is not writable and was opened read-only. {} saves a copy.",
+                keymap.hint(Command::Save)
+            );
+        } else if first.document.has_mixed_line_endings() {
+            initial_status = format!(
+                "File has mixed line endings, which are kept. {} makes them all {}.",
+                keymap.hint(Command::ToggleLineEnding),
+                first.document.line_ending().name()
+            );
+        }
 
-        Self {
+        let mut editor = Self {
             should_quit: false,
-            terminal: Terminal::default().expect("Failed to initialize terminal"),
-            document,
-            cursor_position: Position::default(),
-            offset: Position::default(),
+            terminal,
+            document: first.document,
+            cursor_position: first.cursor_position,
+            offset: first.offset,
             status_message: StatusMessage::from(initial_status),
             quit_times: QUIT_TIMES,
             highlighted_word: None,
+            selection: None,
+            config,
+            buffers,
+            buffer_index: 0,
+            swap_written: None,
+            swap_pending: false,
+            watcher,
+            to_stdout: args.to_stdout,
+            stdin_buffer,
+            last_click: None,
+            pending_keys: Vec::new(),
+            vim: None,
+            kill_ring: KillRing::default(),
+            mark_set: false,
+            last_command: None,
+            yanked: None,
+        };
+        if editor.config.modal {
+            editor.vim = Some(Vim::default());
+        }
+        editor.scroll();
+        Ok(editor)
+    }
+    /// Offers to recover the swap files left behind for any of the open files.
+    fn check_swaps(&mut self) -> Result<(), std::io::Error> {
+        for index in 0..self.buffers.len() {
+            self.switch_buffer(index);
+            self.check_swap()?;
+            if self.should_quit {
+                break;
+            }
+        }
+        self.switch_buffer(0);
+        Ok(())
+    }
+    fn check_swap(&mut self) -> Result<(), std::io::Error> {
+        let path = if let Some(path) = self.document.swap_path() {
+            path
+        } else {
+            return Ok(());
+        };
+        let swap = match swap::read(&path) {
+            Ok(Some(swap)) => swap,
+            Ok(None) => return Ok(()),
+            Err(error) => {
+                self.status_message =
+                    StatusMessage::from(format!("Could not read swap file: {}", error));
+                return Ok(());
+            }
+        };
+        let warning = swap.running_pid.map_or_else(String::new, |pid| {
+            format!(" It may be in use by hecto (pid {}).", pid)
+        });
+        let mut diff_view = None;
+        loop {
+            let question = format!(
+                "Found swap file {}.{} (r)ecover, (d)iff, (x) discard, (q)uit",
+                path.display(),
+                warning
+            );
+            let answer = self.ask(&question, &['r', 'd', 'x', 'q'])?;
+            if let Some((document, cursor_position, offset)) = diff_view.take() {
+                self.document = document;
+                self.cursor_position = cursor_position;
+                self.offset = offset;
+                if answer == 'd' {
+                    continue;
+                }
+            }
+            match answer {
+                'r' => {
+                    self.document.replace_text(&swap.text);
+                    self.cursor_position = start_position(
+                        &self.document,
+                        Some(self.cursor_position.y.saturating_add(1)),
+                        None,
+                    );
+                    self.scroll();
+                    self.status_message = StatusMessage::from(
+                        "Recovered unsaved changes from the swap file.".to_string(),
+                    );
+                }
+                'd' => {
+                    let current = self.document.text();
+                    let current: Vec<&str> = current.lines().collect();
+                    let recovered: Vec<&str> = swap.text.lines().collect();
+                    let mut lines = swap::diff(&current, &recovered);
+                    if lines.is_empty() {
+                        lines.push("The swap file matches the file on disk.".to_string());
+                    }
+                    let document = mem::replace(&mut self.document, Document::scratch(&lines));
+                    diff_view = Some((
+                        document,
+                        mem::take(&mut self.cursor_position),
+                        mem::take(&mut self.offset),
+                    ));
+                    continue;
+                }
+                'x' => swap::remove(&path),
+                _ => self.should_quit = true,
+            }
+            return Ok(());
+        }
+    }
+    /// Writes the swap file for the active document if it has unsaved
+    /// changes and the last write is long enough ago.
+    fn update_swap(&mut self) {
+        if !self.document.is_dirty() {
+            self.swap_written = None;
+            self.swap_pending = false;
+            return;
+        }
+        if self
+            .swap_written
+            .map_or(false, |time| time.elapsed() < SWAP_INTERVAL)
+        {
+            return;
+        }
+        if let Some(path) = self.document.swap_path() {
+            if let Err(error) = swap::write(&path, &self.document.text()) {
+                self.status_message =
+                    StatusMessage::from(format!("Could not write swap file: {}", error));
+            }
+        }
+        self.swap_written = Some(Instant::now());
+        self.swap_pending = false;
+    }
+    fn remove_swaps(&self) {
+        let documents = self
+            .buffers
+            .iter()
+            .map(|buffer| &buffer.document)
+            .chain(Some(&self.document));
+        for path in documents.filter_map(Document::swap_path) {
+            swap::remove(&path);
+        }
+    }
+    /// Offers to reload the active document if its file changed on disk.
+    fn check_disk_change(&mut self) -> Result<(), std::io::Error> {
+        if !self.document.has_new_disk_change() {
+            return Ok(());
+        }
+        let name = self.document.file_name.clone().unwrap_or_default();
+        let question = if self.document.is_dirty() {
+            format!(
+                "{} changed on disk. Reload it and lose your changes? (y/n)",
+                name
+            )
+        } else {
+            format!("{} changed on disk. Reload it? (y/n)", name)
+        };
+        if self.ask(&question, &['y', 'n'])? == 'y' {
+            self.reload();
+        } else {
+            self.document.ignore_disk_change();
+        }
+        Ok(())
+    }
+    /// Reads the active document from disk again, keeping the cursor where
+    /// it was as far as the new contents allow.
+    fn reload(&mut self) {
+        if let Err(error) = self.document.reload() {
+            self.status_message = StatusMessage::from(format!("Could not reload: {}", error));
+            return;
+        }
+        self.cursor_position = start_position(
+            &self.document,
+            Some(self.cursor_position.y.saturating_add(1)),
+            Some(self.cursor_position.x.saturating_add(1)),
+        );
+        self.selection = None;
+        self.swap_written = None;
+        self.scroll();
+        self.status_message = StatusMessage::from("Reloaded from disk.".to_string());
+    }
+    /// Asks `question` until one of `choices` is typed, while the arrow keys
+    /// keep scrolling the document.
+    fn ask(&mut self, question: &str, choices: &[char]) -> Result<char, std::io::Error> {
+        loop {
+            self.status_message = StatusMessage::from(question.to_string());
+            self.refresh_screen()?;
+            match self.terminal.read_key()? {
+                Key::Char(c) if choices.contains(&c.to_ascii_lowercase()) => {
+                    self.status_message = StatusMessage::from(String::new());
+                    return Ok(c.to_ascii_lowercase());
+                }
+                key @ Key::Up
+                | key @ Key::Down
+                | key @ Key::PageUp
+                | key @ Key::PageDown
+                | key @ Key::Home
+                | key @ Key::End => {
+                    self.move_cursor(key);
+                    self.scroll();
+                }
+                _ => (),
+            }
+        }
+    }
+    /// Stashes the active document and makes `buffers[index]` the active one.
+    fn switch_buffer(&mut self, index: usize) {
+        if index == self.buffer_index || index >= self.buffers.len() {
+            return;
+        }
+        // Changes not yet in the swap file would stay out of it until the
+        // buffer is back.
+        if self.swap_pending {
+            self.swap_written = None;
+            self.update_swap();
+        }
+        let current = Buffer {
+            document: mem::take(&mut self.document),
+            cursor_position: self.cursor_position.clone(),
+            offset: self.offset.clone(),
+        };
+        if let Some(slot) = self.buffers.get_mut(self.buffer_index) {
+            *slot = current;
+        }
+        let next = self
+            .buffers
+            .get_mut(index)
+            .map(mem::take)
+            .unwrap_or_default();
+        self.document = next.document;
+        self.cursor_position = next.cursor_position;
+        self.offset = next.offset;
+        self.buffer_index = index;
+        self.selection = None;
+        self.swap_written = None;
+        self.scroll();
+    }
+    fn next_buffer(&mut self, forward: bool) -> Result<(), std::io::Error> {
+        let count = self.buffers.len();
+        if count < 2 {
+            self.status_message = StatusMessage::from("No other files open.".to_string());
+            return Ok(());
+        }
+        #[allow(clippy::integer_arithmetic)]
+        let index = if forward {
+            (self.buffer_index + 1) % count
+        } else {
+            (self.buffer_index + count - 1) % count
+        };
+        self.switch_buffer(index);
+        // Changes to this file were not looked at while it was in the
+        // background.
+        self.check_disk_change()
+    }
+    fn is_dirty(&self) -> bool {
+        let is_dirty = |index: usize, document: &Document| {
+            document.is_dirty() && !(self.to_stdout && self.stdin_buffer == Some(index))
+        };
+        is_dirty(self.buffer_index, &self.document)
+            || self.buffers.iter().enumerate().any(|(index, buffer)| {
+                index != self.buffer_index && is_dirty(index, &buffer.document)
+            })
+    }
+    /// The document that goes to stdout on exit, as it would be written: the
+    /// one read from stdin, or the active one if none was.
+    pub fn contents(&self) -> Result<Vec<u8>, std::io::Error> {
+        match self.stdin_buffer {
+            Some(index) if index != self.buffer_index => self
+                .buffers
+                .get(index)
+                .map_or_else(|| Ok(Vec::new()), |buffer| buffer.document.contents()),
+            _ => self.document.contents(),
         }
     }
 
     fn refresh_screen(&mut self) -> Result<(), std::io::Error> {
-        Terminal::cursor_hide();
-        Terminal::cursor_position(&Position::default());
-        if self.should_quit {
-            Terminal::clear_screen();
-            println!("Goodbye.\r");
-        } else {
-            self.document.highlight(
-                &self.highlighted_word,
-                Some(
-                    self.offset
-                        .y
-                        .saturating_add(self.terminal.size().height as usize),
-                ),
-            );
-            self.draw_rows();
-            self.draw_status_bar();
-            self.draw_message_bar();
-            Terminal::cursor_position(&Position {
-                x: self.cursor_position.x.saturating_sub(self.offset.x),
-                y: self.cursor_position.y.saturating_sub(self.offset.y),
-            });
+        self.terminal.cursor_hide();
+        self.terminal.cursor_position(&Position::default());
+        self.document.highlight(
+            &self.highlighted_word,
+            Some(
+                self.offset
+                    .y
+                    .saturating_add(self.terminal.size().height as usize),
+            ),
+        );
+        self.draw_rows();
+        self.draw_status_bar();
+        self.draw_message_bar();
+        let position = Position {
+            x: self.cursor_render_x().saturating_sub(self.offset.x),
+            y: self.cursor_position.y.saturating_sub(self.offset.y),
+        };
+        self.terminal.cursor_position(&position);
+        // Scrolling with the mouse wheel can leave the cursor out of view.
+        let height = self.terminal.size().height as usize;
+        if self.cursor_position.y >= self.offset.y && position.y < height {
+            self.terminal.cursor_show();
         }
-        Terminal::cursor_show();
-        Terminal::flush()
+        self.terminal.flush()
     }
-    fn save(&mut self) {
+    fn save(&mut self) -> Result<(), std::io::Error> {
+        if self.document.is_read_only() {
+            return self.save_as("File is read-only. Save a copy as: ");
+        }
         if self.document.file_name.is_none() {
-            let new_name = self.prompt("Save as: ", |_, _, _| {}).unwrap_or(None);
-            if new_name.is_none() {
+            return self.save_as("Save as: ");
+        }
+        if self.document.changed_on_disk() {
+            let question = "The file changed on disk since it was read. Overwrite it? (y/n)";
+            if self.ask(question, &['y', 'n'])? != 'y' {
                 self.status_message = StatusMessage::from("Save aborted.".to_string());
-                return;
+                return Ok(());
             }
-            self.document.file_name = new_name;
         }
 
-        if self.document.save().is_ok() {
-            self.status_message = StatusMessage::from("File saved successfully.".to_string());
+        self.status_message = match self.document.save(&self.config.backup) {
+            Ok(()) => StatusMessage::from("File saved successfully.".to_string()),
+            Err(error) => StatusMessage::from(format!("Error writing file: {}", error)),
+        };
+        Ok(())
+    }
+    /// Saves the active document under a new name, which it is edited as
+    /// from then on.
+    fn save_as(&mut self, prompt: &str) -> Result<(), std::io::Error> {
+        let new_name = if let Some(new_name) = self.ask_file_name(prompt, "Save aborted.")? {
+            new_name
+        } else {
+            return Ok(());
+        };
+        let old_swap = self.document.swap_path();
+        let old_name = self.document.file_name.replace(new_name.clone());
+        self.status_message = match self.document.save(&self.config.backup) {
+            Ok(()) => {
+                self.document.set_read_only(false);
+                if let Some(path) = old_swap {
+                    swap::remove(&path);
+                }
+                self.watcher.watch(Path::new(&new_name));
+                StatusMessage::from(format!("Saved as {}.", new_name))
+            }
+            Err(error) => {
+                self.document.file_name = old_name;
+                StatusMessage::from(format!("Error writing file: {}", error))
+            }
+        };
+        Ok(())
+    }
+    /// Renames the active document's file, on disk as well if it is there.
+    fn rename(&mut self) -> Result<(), std::io::Error> {
+        let old_name = if let Some(old_name) = self.document.file_name.clone() {
+            old_name
+        } else {
+            return self.save_as("Save as: ");
+        };
+        let prompt = format!("Rename {} to: ", old_name);
+        let new_name = if let Some(new_name) = self.ask_file_name(&prompt, "Rename aborted.")? {
+            new_name
         } else {
-            self.status_message = StatusMessage::from("Error writing file!".to_string());
+            return Ok(());
+        };
+        if Path::new(&old_name).exists() {
+            if let Err(error) = fs::rename(&old_name, &new_name) {
+                self.status_message = StatusMessage::from(format!("Could not rename: {}", error));
+                return Ok(());
+            }
+        }
+        if let Some(path) = self.document.swap_path() {
+            swap::remove(&path);
         }
+        self.document.set_file_name(&new_name);
+        self.watcher.watch(Path::new(&new_name));
+        self.swap_written = None;
+        self.status_message = StatusMessage::from(format!("Renamed to {}.", new_name));
+        Ok(())
+    }
+    /// Writes the selected lines, or the current one, to another file.
+    fn write_selection(&mut self) -> Result<(), std::io::Error> {
+        let lines = self.selected_lines();
+        let prompt = format!(
+            "Write lines {}-{} to: ",
+            lines.start.saturating_add(1),
+            lines.end
+        );
+        let name = if let Some(name) = self.ask_file_name(&prompt, "Write aborted.")? {
+            name
+        } else {
+            return Ok(());
+        };
+        let count = lines.len();
+        self.status_message = match self.document.write_rows(Path::new(&name), lines) {
+            Ok(()) => StatusMessage::from(format!("Wrote {} lines to {}.", count, name)),
+            Err(error) => StatusMessage::from(format!("Error writing file: {}", error)),
+        };
+        Ok(())
+    }
+    /// Prompts for the name of a file to write to, confirming before an
+    /// existing file is overwritten and offering to create missing
+    /// directories. Returns `None` if the user backs out, showing `aborted`,
+    /// or if a directory cannot be created, showing why.
+    fn ask_file_name(
+        &mut self,
+        prompt: &str,
+        aborted: &str,
+    ) -> Result<Option<String>, std::io::Error> {
+        let name = self.prompt(prompt, |_, _, _| {})?;
+        let confirmed = match &name {
+            Some(name) => self.confirm_file_name(name)?,
+            None => false,
+        };
+        if !confirmed {
+            self.status_message = StatusMessage::from(aborted.to_string());
+            return Ok(None);
+        }
+        let name = name.unwrap_or_default();
+        if let Some(dir) = Path::new(&name)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+        {
+            if let Err(error) = fs::create_dir_all(dir) {
+                self.status_message =
+                    StatusMessage::from(format!("Could not create {}: {}", dir.display(), error));
+                return Ok(None);
+            }
+        }
+        Ok(Some(name))
+    }
+    /// Asks before `name` overwrites a file or needs a new directory.
+    fn confirm_file_name(&mut self, name: &str) -> Result<bool, std::io::Error> {
+        let path = Path::new(name);
+        let question = if path.exists() {
+            format!("{} already exists. Overwrite it? (y/n)", name)
+        } else if let Some(dir) = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+        {
+            format!("{} does not exist. Create it? (y/n)", dir.display())
+        } else {
+            return Ok(true);
+        };
+        Ok(self.ask(&question, &['y', 'n'])? == 'y')
     }
     fn search(&mut self) {
         let old_position = self.cursor_position.clone();
@@ -171,13 +720,98 @@ impl Editor {
         }
         self.highlighted_word = None;
     }
-    fn process_keypress(&mut self) -> Result<(), std::io::Error> {
-        let pressed_key = Terminal::read_key()?;
-        match pressed_key {
-            Key::Ctrl('q') => {
-                if self.quit_times > 0 && self.document.is_dirty() {
+    fn go_to_line(&mut self) {
+        let input = self
+            .prompt("Go to line (line[:col], +N, -N or N%): ", |_, _, _| {})
+            .unwrap_or(None);
+        let input = if let Some(input) = input {
+            input
+        } else {
+            return;
+        };
+        if let Some((y, x)) = parse_goto(&input, self.cursor_position.y, self.document.len()) {
+            let width = self.document.row(y).map_or(0, Row::len);
+            self.selection = None;
+            self.cursor_position = Position {
+                x: x.map_or(0, |x| x.min(width)),
+                y,
+            };
+            self.offset = centered_offset(self.terminal.size(), y);
+            self.scroll();
+        } else {
+            self.status_message = StatusMessage::from(format!("Invalid line: {}", input));
+        }
+    }
+    fn toggle_line_ending(&mut self) {
+        // Mixed line endings are first made all the same.
+        let line_ending = match self.document.line_ending() {
+            line_ending if self.document.has_mixed_line_endings() => line_ending,
+            LineEnding::Lf => LineEnding::CrLf,
+            LineEnding::CrLf => LineEnding::Lf,
+        };
+        self.document.set_line_ending(line_ending);
+        self.status_message =
+            StatusMessage::from(format!("Line endings set to {}.", line_ending.name()));
+    }
+    /// Runs the command bound to the keys pressed so far, or handles an
+    /// unbound key as input. The first keys of a chord are only collected.
+    fn process_keypress(&mut self, pressed_key: Key) -> Result<(), std::io::Error> {
+        let pressed_key = match self.mode() {
+            _ if !self.pending_keys.is_empty() => pressed_key,
+            Some(Mode::Insert) if pressed_key == Key::Esc => {
+                self.set_mode(Mode::Normal);
+                self.cursor_position.x = self.cursor_position.x.saturating_sub(1);
+                self.finish_key();
+                return Ok(());
+            }
+            Some(Mode::Normal | Mode::Visual) => {
+                if let Some(key) = self.vim_keypress(pressed_key)? {
+                    key
+                } else {
+                    return Ok(());
+                }
+            }
+            _ => pressed_key,
+        };
+        self.pending_keys.push(pressed_key);
+        match self.config.keymap.lookup(&self.pending_keys) {
+            Binding::Command(command) => {
+                self.pending_keys.clear();
+                self.run_command(command)
+            }
+            Binding::Prefix => {
+                self.status_message =
+                    StatusMessage::from(format!("{}-", format_keys(&self.pending_keys)));
+                Ok(())
+            }
+            Binding::Unbound => {
+                let mut keys = mem::take(&mut self.pending_keys);
+                if keys.len() > 1 {
+                    self.status_message =
+                        StatusMessage::from(format!("{} is not bound", format_keys(&keys)));
+                    return Ok(());
+                }
+                if let Some(key) = keys.pop() {
+                    self.process_input(key);
+                }
+                Ok(())
+            }
+        }
+    }
+    fn run_command(&mut self, command: Command) -> Result<(), std::io::Error> {
+        if self.document.is_read_only() && command.is_edit() {
+            self.refuse_edit();
+            return Ok(());
+        }
+        match command {
+            Command::Help => self.show_help()?,
+            // The command picked does its own finishing.
+            Command::Palette => return self.command_palette(),
+            Command::Quit => {
+                if self.quit_times > 0 && self.is_dirty() {
                     self.status_message = StatusMessage::from(format!(
-                        "WARNING! File has unsaved changes. Press Ctrl-Q {} more times to quit.",
+                        "WARNING! File has unsaved changes. Press {} {} more times to quit.",
+                        self.config.keymap.hint(Command::Quit),
                         self.quit_times
                     ));
                     self.quit_times -= 1;
@@ -185,41 +819,903 @@ impl Editor {
                 }
                 self.should_quit = true
             }
-            Key::Ctrl('s') => self.save(),
-            Key::Ctrl('f') => self.search(),
-            Key::Char(c) => {
-                self.document.insert(&self.cursor_position, c);
-                self.move_cursor(Key::Right);
+            Command::Save => self.save()?,
+            Command::SaveAs => self.save_as("Save as: ")?,
+            Command::Rename => self.rename()?,
+            Command::WriteSelection => self.write_selection()?,
+            Command::Find => self.search(),
+            Command::GoToLine => self.go_to_line(),
+            Command::NextBuffer => self.next_buffer(true)?,
+            Command::PreviousBuffer => self.next_buffer(false)?,
+            Command::ToggleLineEnding => self.toggle_line_ending(),
+            Command::Indent => {
+                if self.selection_range().is_some() {
+                    self.shift_lines(false);
+                } else {
+                    self.cursor_position = self.document.insert_tab(&self.cursor_position);
+                }
             }
-            Key::Delete => self.document.delete(&self.cursor_position),
-            Key::Backspace => {
-                if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
-                    self.move_cursor(Key::Left);
+            Command::Outdent => self.shift_lines(true),
+            Command::DeleteForward => {
+                if !self.delete_selection() {
                     self.document.delete(&self.cursor_position);
                 }
             }
-            Key::Up
-            | Key::Down
-            | Key::Left
-            | Key::Right
-            | Key::PageUp
-            | Key::PageDown
-            | Key::End
-            | Key::Home => self.move_cursor(pressed_key),
+            Command::DeleteBackward => {
+                if !self.delete_selection() {
+                    self.backspace();
+                }
+            }
+            Command::DeleteWordForward => {
+                if !self.delete_selection() {
+                    self.document.delete_word_forward(&self.cursor_position);
+                }
+            }
+            Command::DeleteWordBackward => {
+                if !self.delete_selection() {
+                    self.cursor_position =
+                        self.document.delete_word_backward(&self.cursor_position);
+                }
+            }
+            Command::ClearSelection => self.selection = None,
+            Command::MoveUp => self.move_or_select(Key::Up, false),
+            Command::MoveDown => self.move_or_select(Key::Down, false),
+            Command::MoveLeft => self.move_or_select(Key::Left, false),
+            Command::MoveRight => self.move_or_select(Key::Right, false),
+            Command::MoveWordLeft => self.move_or_select(Key::CtrlLeft, false),
+            Command::MoveWordRight => self.move_or_select(Key::CtrlRight, false),
+            Command::MoveLineStart => self.move_or_select(Key::Home, false),
+            Command::MoveLineEnd => self.move_or_select(Key::End, false),
+            Command::PageUp => self.move_or_select(Key::PageUp, false),
+            Command::PageDown => self.move_or_select(Key::PageDown, false),
+            Command::SelectUp => self.move_or_select(Key::Up, true),
+            Command::SelectDown => self.move_or_select(Key::Down, true),
+            Command::SelectLeft => self.move_or_select(Key::Left, true),
+            Command::SelectRight => self.move_or_select(Key::Right, true),
+            Command::SelectWordLeft => self.move_or_select(Key::CtrlLeft, true),
+            Command::SelectWordRight => self.move_or_select(Key::CtrlRight, true),
+            Command::SelectLineStart => self.move_or_select(Key::Home, true),
+            Command::SelectLineEnd => self.move_or_select(Key::End, true),
+            Command::SetMark => {
+                self.selection = Some(self.cursor_position.clone());
+                self.mark_set = true;
+                self.status_message = StatusMessage::from("Mark set".to_string());
+            }
+            Command::KillLine => self.kill_line(),
+            Command::KillRegion => self.kill_region(),
+            Command::CopyRegion => self.copy_region(),
+            Command::Yank => self.yank(),
+            Command::YankPop => self.yank_pop(),
+        }
+        self.last_command = Some(command);
+        self.finish_key();
+        Ok(())
+    }
+    /// Handles a key that is not bound to a command: typed and pasted text
+    /// and the mouse.
+    fn process_input(&mut self, key: Key) {
+        self.last_command = None;
+        if self.document.is_read_only() && matches!(key, Key::Char(_) | Key::Paste(_)) {
+            self.refuse_edit();
+            return;
+        }
+        match key {
+            Key::Char(c) => self.insert_char(c),
+            Key::Paste(text) => self.paste(&text),
+            Key::Click(column, row) => self.click(column, row),
+            Key::Drag(column, row) => {
+                if let Some(position) = self.position_at(column, row) {
+                    if self.selection.is_none() {
+                        self.selection = Some(self.cursor_position.clone());
+                    }
+                    self.cursor_position = position;
+                }
+            }
+            Key::WheelUp | Key::WheelDown => {
+                self.scroll_view(key == Key::WheelDown);
+                return;
+            }
             _ => (),
         }
+        self.finish_key();
+    }
+    fn refuse_edit(&mut self) {
+        self.status_message = StatusMessage::from(format!(
+            "File is read-only. {} saves an editable copy.",
+            self.config.keymap.hint(Command::Save)
+        ));
+    }
+    /// Scrolls to the cursor and drops a pending quit warning.
+    fn finish_key(&mut self) {
+        if matches!(self.mode(), Some(Mode::Normal | Mode::Visual)) {
+            // Outside insert mode the cursor stays on a character.
+            let y = self
+                .cursor_position
+                .y
+                .min(self.document.len().saturating_sub(1));
+            let width = self.document.row(y).map_or(0, Row::len);
+            self.cursor_position = Position {
+                x: self.cursor_position.x.min(width.saturating_sub(1)),
+                y,
+            };
+        }
+        if self.selection.is_none() {
+            self.mark_set = false;
+        }
         self.scroll();
         if self.quit_times < QUIT_TIMES {
             self.quit_times = QUIT_TIMES;
             self.status_message = StatusMessage::from(String::new());
         }
+    }
+    /// Moves the cursor as `key` would, extending the selection if `select`
+    /// is set and clearing it otherwise.
+    fn move_or_select(&mut self, key: Key, select: bool) {
+        if !select && !self.mark_set {
+            self.selection = None;
+        } else if self.selection.is_none() {
+            self.selection = Some(self.cursor_position.clone());
+        }
+        self.move_cursor(key);
+    }
+    /// Kills from the cursor to the end of the line, or the line break at
+    /// the end of a line.
+    fn kill_line(&mut self) {
+        let start = self.cursor_position.clone();
+        let width = self.document.row(start.y).map_or(0, Row::len);
+        let end = if start.x < width {
+            Position {
+                x: width,
+                y: start.y,
+            }
+        } else if start.y.saturating_add(1) < self.document.len() {
+            Position {
+                x: 0,
+                y: start.y.saturating_add(1),
+            }
+        } else {
+            return;
+        };
+        let text = self.document.text_between(&start, &end);
+        self.document.delete_range(&start, &end);
+        self.kill(text);
+    }
+    fn kill_region(&mut self) {
+        if self.selection.is_none() {
+            self.status_message = StatusMessage::from("The mark is not set".to_string());
+            return;
+        }
+        if let Some((start, end)) = self.selection_range() {
+            let text = self.document.text_between(&start, &end);
+            self.document.delete_range(&start, &end);
+            self.cursor_position = start;
+            self.kill(text);
+        }
+        self.selection = None;
+    }
+    fn copy_region(&mut self) {
+        if let Some((start, end)) = self.selection_range() {
+            let text = self.document.text_between(&start, &end);
+            self.kill_ring.kill(text, false);
+        }
+        self.selection = None;
+    }
+    /// Adds `text` to the kill ring, joining it to the last kill if that was
+    /// the command before.
+    fn kill(&mut self, text: String) {
+        let append = matches!(
+            self.last_command,
+            Some(Command::KillLine | Command::KillRegion)
+        );
+        self.kill_ring.kill(text, append);
+    }
+    fn yank(&mut self) {
+        if let Some(text) = self.kill_ring.yank().map(str::to_string) {
+            self.delete_selection();
+            self.insert_yank(&text);
+        } else {
+            self.status_message = StatusMessage::from("The kill ring is empty".to_string());
+        }
+    }
+    /// Replaces the text just yanked with the kill before it.
+    fn yank_pop(&mut self) {
+        let yanked = match (self.last_command, &self.yanked) {
+            (Some(Command::Yank | Command::YankPop), Some(yanked)) => yanked.clone(),
+            _ => {
+                self.status_message =
+                    StatusMessage::from("The last command was not a yank".to_string());
+                return;
+            }
+        };
+        if let Some(text) = self.kill_ring.yank_pop().map(str::to_string) {
+            let (start, end) = yanked;
+            self.document.delete_range(&start, &end);
+            self.cursor_position = start;
+            self.insert_yank(&text);
+        }
+    }
+    fn insert_yank(&mut self, text: &str) {
+        let start = self.cursor_position.clone();
+        self.cursor_position = self.document.insert_str(&start, text);
+        self.yanked = Some((start, self.cursor_position.clone()));
+    }
+    fn mode(&self) -> Option<Mode> {
+        self.vim.as_ref().map(|vim| vim.mode)
+    }
+    fn set_mode(&mut self, mode: Mode) {
+        if let Some(vim) = &mut self.vim {
+            vim.mode = mode;
+            vim.pending.clear();
+        }
+        self.selection = if mode == Mode::Visual {
+            Some(self.cursor_position.clone())
+        } else {
+            None
+        };
+    }
+    /// Handles a key in normal or visual mode. Keys that modal editing
+    /// leaves alone, such as `Ctrl-S`, are handed back.
+    fn vim_keypress(&mut self, key: Key) -> Result<Option<Key>, std::io::Error> {
+        let c = match key {
+            Key::Char(c) if !c.is_control() => c,
+            Key::Left | Key::Backspace => 'h',
+            Key::Right => 'l',
+            Key::Up => 'k',
+            Key::Down | Key::Char('\n') => 'j',
+            Key::Home => '0',
+            Key::End => '$',
+            Key::Esc => {
+                self.set_mode(Mode::Normal);
+                return Ok(None);
+            }
+            Key::Char(_) => return Ok(None),
+            key => {
+                if let Some(vim) = &mut self.vim {
+                    vim.pending.clear();
+                }
+                return Ok(Some(key));
+            }
+        };
+        let keys = if let Some(vim) = &mut self.vim {
+            vim.pending.push(c);
+            vim.pending.clone()
+        } else {
+            return Ok(None);
+        };
+        let parsed = if self.mode() == Some(Mode::Visual) {
+            self.visual_keypress(&keys)
+        } else {
+            vim::parse(&keys)
+        };
+        match parsed {
+            vim::Parse::Pending => return Ok(None),
+            vim::Parse::Invalid => (),
+            vim::Parse::Done(count, action) => {
+                if let Some(vim) = &mut self.vim {
+                    vim.pending.clear();
+                }
+                self.vim_action(count, action)?;
+            }
+        }
+        if let Some(vim) = &mut self.vim {
+            vim.pending.clear();
+        }
+        self.finish_key();
+        Ok(None)
+    }
+    /// Handles the keys typed in visual mode, where operators work on the
+    /// selection and text objects select. Motions parse as in normal mode.
+    fn visual_keypress(&mut self, keys: &str) -> vim::Parse {
+        let operator = match keys {
+            "d" | "x" => Some(Operator::Delete),
+            "c" => Some(Operator::Change),
+            "y" => Some(Operator::Yank),
+            "v" => {
+                self.set_mode(Mode::Normal);
+                return vim::Parse::Invalid;
+            }
+            _ => None,
+        };
+        let anchor = self
+            .selection
+            .clone()
+            .unwrap_or_else(|| self.cursor_position.clone());
+        if let Some(operator) = operator {
+            if self.document.is_read_only() && operator != Operator::Yank {
+                self.refuse_edit();
+                return vim::Parse::Invalid;
+            }
+            let (start, mut end) = ordered(anchor, self.cursor_position.clone());
+            // The selection takes in the character under the cursor.
+            let width = self.document.row(end.y).map_or(0, Row::len);
+            end.x = end.x.saturating_add(1).min(width);
+            self.set_mode(Mode::Normal);
+            self.operate(operator, &start, &end, false);
+            return vim::Parse::Invalid;
+        }
+        if keys.starts_with(['i', 'a']) {
+            return match vim::parse(&format!("y{}", keys)) {
+                vim::Parse::Done(_, Action::Operate(_, Target::Object { object, inner })) => {
+                    let range =
+                        vim::object_range(&self.document, object, inner, &self.cursor_position);
+                    if let Some((start, end)) = range {
+                        self.selection = Some(start);
+                        self.cursor_position =
+                            vim::motion_target(&self.document, Motion::Left, None, &end);
+                    }
+                    vim::Parse::Invalid
+                }
+                parsed => parsed,
+            };
+        }
+        match vim::parse(keys) {
+            parsed @ (vim::Parse::Pending | vim::Parse::Done(_, Action::Move(_))) => parsed,
+            _ => vim::Parse::Invalid,
+        }
+    }
+    fn vim_action(&mut self, count: Option<usize>, action: Action) -> Result<(), std::io::Error> {
+        let is_edit = !matches!(
+            action,
+            Action::Move(_)
+                | Action::Visual
+                | Action::CommandLine
+                | Action::Operate(Operator::Yank, _)
+        );
+        if self.document.is_read_only() && is_edit {
+            self.refuse_edit();
+            return Ok(());
+        }
+        let Position { x, y } = self.cursor_position;
+        let row = self.document.row(y);
+        let width = row.map_or(0, Row::len);
+        let indent = row.map_or_else(String::new, |row| row.leading_whitespace().to_string());
+        match action {
+            Action::Move(motion) => {
+                self.cursor_position =
+                    vim::motion_target(&self.document, motion, count, &self.cursor_position);
+            }
+            Action::Operate(operator, target) => self.vim_operate(operator, target, count),
+            Action::Insert => self.set_mode(Mode::Insert),
+            Action::Append => {
+                self.cursor_position.x = x.saturating_add(1).min(width);
+                self.set_mode(Mode::Insert);
+            }
+            Action::InsertAtLineStart => {
+                self.cursor_position.x = row.map_or(0, vim::first_non_blank);
+                self.set_mode(Mode::Insert);
+            }
+            Action::AppendAtLineEnd => {
+                self.cursor_position.x = width;
+                self.set_mode(Mode::Insert);
+            }
+            Action::OpenBelow => {
+                let end = Position { x: width, y };
+                self.cursor_position = self.document.insert_str(&end, &format!("\n{}", indent));
+                self.set_mode(Mode::Insert);
+            }
+            Action::OpenAbove => {
+                self.document
+                    .insert_str(&Position { x: 0, y }, &format!("{}\n", indent));
+                self.cursor_position = Position {
+                    x: indent.graphemes(true).count(),
+                    y,
+                };
+                self.set_mode(Mode::Insert);
+            }
+            Action::DeleteChar => {
+                let end = Position {
+                    x: x.saturating_add(count.unwrap_or(1)).min(width),
+                    y,
+                };
+                if end.x > x {
+                    self.operate(Operator::Delete, &Position { x, y }, &end, false);
+                }
+            }
+            Action::Put { before } => self.put(before, count.unwrap_or(1)),
+            Action::Visual => self.set_mode(Mode::Visual),
+            Action::CommandLine => self.vim_command_line()?,
+        }
         Ok(())
     }
-    fn scroll(&mut self) {
+    /// Runs an operator on the text from the cursor to a motion, on a text
+    /// object or on whole lines.
+    fn vim_operate(&mut self, operator: Operator, target: Target, count: Option<usize>) {
+        let at = self.cursor_position.clone();
+        let (start, end, linewise) = match target {
+            Target::Lines => {
+                let last = at.y.saturating_add(count.unwrap_or(1).saturating_sub(1));
+                (at.clone(), Position { x: 0, y: last }, true)
+            }
+            Target::Motion(motion) => {
+                // `cw` changes to the end of the word, like `ce`.
+                let motion = if operator == Operator::Change && motion == Motion::WordStart {
+                    Motion::WordEnd
+                } else {
+                    motion
+                };
+                let target = vim::motion_target(&self.document, motion, count, &at);
+                let (start, mut end) = ordered(at, target);
+                if motion.is_inclusive() {
+                    let width = self.document.row(end.y).map_or(0, Row::len);
+                    end.x = end.x.saturating_add(1).min(width);
+                }
+                if motion == Motion::WordStart && end.y > start.y {
+                    // A word motion does not take an operator past the line.
+                    end = Position {
+                        x: self.document.row(start.y).map_or(0, Row::len),
+                        y: start.y,
+                    };
+                }
+                (start, end, motion.is_linewise())
+            }
+            Target::Object { object, inner } => {
+                match vim::object_range(&self.document, object, inner, &at) {
+                    Some((start, end)) => (start, end, false),
+                    None => return,
+                }
+            }
+        };
+        self.operate(operator, &start, &end, linewise);
+    }
+    /// Yanks, deletes or changes the text from `start` up to `end`, or the
+    /// lines from `start` to `end` if `linewise` is set.
+    fn operate(&mut self, operator: Operator, start: &Position, end: &Position, linewise: bool) {
+        let last = end.y.min(self.document.len().saturating_sub(1));
+        let register = if linewise {
+            let width = self.document.row(last).map_or(0, Row::len);
+            let lines = self.document.text_between(
+                &Position { x: 0, y: start.y },
+                &Position { x: width, y: last },
+            );
+            Register {
+                text: format!("{}\n", lines),
+                linewise: true,
+            }
+        } else {
+            Register {
+                text: self.document.text_between(start, end),
+                linewise: false,
+            }
+        };
+        if let Some(vim) = &mut self.vim {
+            vim.register = register;
+        }
+        self.cursor_position = if linewise {
+            Position { x: 0, y: start.y }
+        } else {
+            start.clone()
+        };
+        match (operator, linewise) {
+            (Operator::Yank, _) => (),
+            (Operator::Delete, true) => {
+                self.document.delete_lines(start.y, last);
+                let y = start.y.min(self.document.len().saturating_sub(1));
+                self.cursor_position = Position {
+                    x: self.document.row(y).map_or(0, vim::first_non_blank),
+                    y,
+                };
+            }
+            (Operator::Delete, false) => self.document.delete_range(start, end),
+            (Operator::Change, true) => {
+                let width = self.document.row(last).map_or(0, Row::len);
+                self.document
+                    .delete_range(&self.cursor_position, &Position { x: width, y: last });
+            }
+            (Operator::Change, false) => self.document.delete_range(start, end),
+        }
+        if operator == Operator::Change {
+            self.set_mode(Mode::Insert);
+        }
+    }
+    /// Puts the register after the cursor, or before it with `before`,
+    /// `count` times. Lines go on lines of their own.
+    fn put(&mut self, before: bool, count: usize) {
+        let (text, linewise) = match &self.vim {
+            Some(vim) if !vim.register.text.is_empty() => {
+                (vim.register.text.repeat(count), vim.register.linewise)
+            }
+            _ => return,
+        };
+        let Position { x, y } = self.cursor_position;
+        let width = self.document.row(y).map_or(0, Row::len);
+        if linewise {
+            let y = if before {
+                self.document.insert_str(&Position { x: 0, y }, &text);
+                y
+            } else if y.saturating_add(1) < self.document.len() {
+                let below = Position {
+                    x: 0,
+                    y: y.saturating_add(1),
+                };
+                self.document.insert_str(&below, &text);
+                below.y
+            } else {
+                let lines = text.strip_suffix('\n').unwrap_or(&text);
+                let end = Position { x: width, y };
+                self.document.insert_str(&end, &format!("\n{}", lines));
+                y.saturating_add(1)
+            };
+            self.cursor_position = Position {
+                x: self.document.row(y).map_or(0, vim::first_non_blank),
+                y,
+            };
+        } else {
+            let x = if before {
+                x
+            } else {
+                x.saturating_add(1).min(width)
+            };
+            let end = self.document.insert_str(&Position { x, y }, &text);
+            self.cursor_position = Position {
+                x: end.x.saturating_sub(1),
+                y: end.y,
+            };
+        }
+    }
+    /// Runs a command typed after `:`: `w`, `q`, `q!`, `wq`, `x` or a line
+    /// number.
+    fn vim_command_line(&mut self) -> Result<(), std::io::Error> {
+        let input = if let Some(input) = self.prompt(":", |_, _, _| {})? {
+            input
+        } else {
+            return Ok(());
+        };
+        match input.trim() {
+            "w" => self.save()?,
+            "q" => {
+                if self.is_dirty() {
+                    self.status_message = StatusMessage::from(
+                        "No write since last change (add ! to override)".to_string(),
+                    );
+                } else {
+                    self.should_quit = true;
+                }
+            }
+            "q!" => self.should_quit = true,
+            "wq" | "x" => {
+                self.save()?;
+                self.should_quit = !self.is_dirty();
+            }
+            command => {
+                if let Ok(line) = command.parse() {
+                    self.cursor_position = vim::motion_target(
+                        &self.document,
+                        Motion::FirstLine,
+                        Some(line),
+                        &self.cursor_position,
+                    );
+                } else {
+                    self.status_message =
+                        StatusMessage::from(format!("Not an editor command: {}", command));
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Shows the key bindings in place of the document until a key other
+    /// than a scrolling one is pressed.
+    fn show_help(&mut self) -> Result<(), std::io::Error> {
+        let mut lines = self.config.keymap.help_lines();
+        if self.vim.is_some() {
+            lines.extend(vim::HELP.iter().map(|line| line.to_string()));
+        }
+        let help = Document::scratch(&lines);
+        let document = mem::replace(&mut self.document, help);
+        let cursor_position = mem::take(&mut self.cursor_position);
+        let offset = mem::take(&mut self.offset);
+        let selection = self.selection.take();
+        let result = loop {
+            self.status_message =
+                StatusMessage::from("Key bindings. Arrows scroll, any other key closes.".into());
+            self.scroll();
+            if let Err(error) = self.refresh_screen() {
+                break Err(error);
+            }
+            match self.terminal.read_key() {
+                Ok(
+                    key
+                    @ (Key::Up | Key::Down | Key::PageUp | Key::PageDown | Key::Home | Key::End),
+                ) => self.move_cursor(key),
+                Ok(_) => break Ok(()),
+                Err(error) => break Err(error),
+            }
+        };
+        self.document = document;
+        self.cursor_position = cursor_position;
+        self.offset = offset;
+        self.selection = selection;
+        self.status_message = StatusMessage::from(String::new());
+        result
+    }
+    /// Lists every command in place of the document, narrowed down by fuzzy
+    /// matching on its name as the user types, and runs the one picked.
+    fn command_palette(&mut self) -> Result<(), std::io::Error> {
+        let commands: Vec<Command> = Command::ALL
+            .iter()
+            .copied()
+            .filter(|command| *command != Command::Palette)
+            .collect();
+        let document = mem::take(&mut self.document);
+        let cursor_position = mem::take(&mut self.cursor_position);
+        let offset = mem::take(&mut self.offset);
+        let selection = self.selection.take();
+        let mut matches = commands.clone();
+        let mut selected = 0;
+        self.show_commands(&matches, selected);
+        let input = self.prompt_input("Command (Up/Down to pick): ", |editor, key, query| {
+            match key {
+                Key::Up => selected = selected.saturating_sub(1),
+                Key::Down => {
+                    selected = selected
+                        .saturating_add(1)
+                        .min(matches.len().saturating_sub(1));
+                }
+                _ => {
+                    matches = fuzzy::filter(query, commands.iter().map(|command| command.name()))
+                        .into_iter()
+                        .filter_map(|index| commands.get(index).copied())
+                        .collect();
+                    selected = 0;
+                }
+            }
+            editor.show_commands(&matches, selected);
+        });
+        self.document = document;
+        self.cursor_position = cursor_position;
+        self.offset = offset;
+        self.selection = selection;
+        match (input?, matches.get(selected)) {
+            (Some(_), Some(command)) => self.run_command(*command),
+            _ => Ok(()),
+        }
+    }
+    /// Shows `commands` with their bindings in place of the document, with the
+    /// `selected` one highlighted.
+    fn show_commands(&mut self, commands: &[Command], selected: usize) {
+        let lines: Vec<String> = if commands.is_empty() {
+            vec!["No matching commands".to_string()]
+        } else {
+            commands
+                .iter()
+                .map(|command| self.config.keymap.help_line(*command))
+                .collect()
+        };
+        self.document = Document::scratch(&lines);
+        self.cursor_position = Position { x: 0, y: selected };
+        self.selection = (!commands.is_empty()).then(|| Position {
+            x: self.document.row(selected).map_or(0, Row::len),
+            y: selected,
+        });
+        self.offset = Position::default();
+        self.scroll();
+    }
+    /// The document position shown at a 1-based terminal column and row, if
+    /// that is in the text area.
+    fn position_at(&self, column: u16, row: u16) -> Option<Position> {
+        let row = usize::from(row).checked_sub(1)?;
+        if row >= self.terminal.size().height as usize {
+            return None;
+        }
+        let y = self
+            .offset
+            .y
+            .saturating_add(row)
+            .min(self.document.len().saturating_sub(1));
+        let column = self
+            .offset
+            .x
+            .saturating_add(usize::from(column).saturating_sub(1));
+        let tab_width = self.document.indentation().width;
+        let x = self
+            .document
+            .row(y)
+            .map_or(0, |row| row.x_at_render_x(column, tab_width));
+        Some(Position { x, y })
+    }
+    /// Moves the cursor to a click. A second click on the same spot selects
+    /// the word there.
+    fn click(&mut self, column: u16, row: u16) {
+        let position = if let Some(position) = self.position_at(column, row) {
+            position
+        } else {
+            return;
+        };
+        let is_double = self.last_click.as_ref().map_or(false, |(time, last)| {
+            *last == position && time.elapsed() < DOUBLE_CLICK_TIME
+        });
+        if is_double {
+            let word = self
+                .document
+                .row(position.y)
+                .map_or(position.x..position.x, |row| row.word_at(position.x));
+            self.selection = Some(Position {
+                x: word.start,
+                y: position.y,
+            });
+            self.cursor_position = Position {
+                x: word.end,
+                y: position.y,
+            };
+            self.last_click = None;
+        } else {
+            self.selection = None;
+            self.cursor_position = position.clone();
+            self.last_click = Some((Instant::now(), position));
+        }
+    }
+    /// Scrolls the view by a few lines without moving the cursor.
+    fn scroll_view(&mut self, down: bool) {
+        self.offset.y = if down {
+            self.offset
+                .y
+                .saturating_add(WHEEL_LINES)
+                .min(self.document.len().saturating_sub(1))
+        } else {
+            self.offset.y.saturating_sub(WHEEL_LINES)
+        };
+    }
+    /// The selected text as an ordered `(start, end)` pair, if any.
+    fn selection_range(&self) -> Option<(Position, Position)> {
+        let anchor = self.selection.as_ref()?;
+        let cursor = &self.cursor_position;
+        if anchor == cursor {
+            None
+        } else if (anchor.y, anchor.x) < (cursor.y, cursor.x) {
+            Some((anchor.clone(), cursor.clone()))
+        } else {
+            Some((cursor.clone(), anchor.clone()))
+        }
+    }
+    fn delete_selection(&mut self) -> bool {
+        let range = self.selection_range();
+        self.selection = None;
+        if let Some((start, end)) = range {
+            self.document.delete_range(&start, &end);
+            self.cursor_position = start;
+            true
+        } else {
+            false
+        }
+    }
+    fn char_at(&self, x: usize) -> Option<char> {
+        self.document.row(self.cursor_position.y)?.char_at(x)
+    }
+    /// Inserts pasted text as it is, replacing the selection, without any
+    /// of the automatic indentation or pairing typing gets.
+    fn paste(&mut self, text: &str) {
+        self.delete_selection();
+        self.cursor_position = self.document.insert_str(&self.cursor_position, text);
+    }
+    fn insert_char(&mut self, c: char) {
+        if self.config.auto_pairs && self.insert_pair(c) {
+            return;
+        }
+        self.delete_selection();
+        self.cursor_position = self.document.insert(&self.cursor_position, c);
+    }
+    /// Handles `c` as part of a bracket or quote pair. Returns `false` if `c`
+    /// should just be inserted as usual.
+    fn insert_pair(&mut self, c: char) -> bool {
+        let closer = self
+            .document
+            .pairs()
+            .iter()
+            .find(|(open, _)| *open == c)
+            .map(|(_, close)| *close);
+        if let Some((start, end)) = self.selection_range() {
+            if let Some(closer) = closer {
+                self.selection = None;
+                self.document.insert_str(&end, &closer.to_string());
+                let after_opener = self.document.insert_str(&start, &c.to_string());
+                self.cursor_position = if start.y == end.y {
+                    Position {
+                        x: end.x.saturating_add(1),
+                        y: end.y,
+                    }
+                } else {
+                    end
+                };
+                self.selection = Some(after_opener);
+                return true;
+            }
+            return false;
+        }
+        let x = self.cursor_position.x;
+        let next = self.char_at(x);
+        let is_closer = self.document.pairs().iter().any(|(_, close)| *close == c);
+        if is_closer && next == Some(c) {
+            self.move_cursor(Key::Right);
+            return true;
+        }
+        let closer = if let Some(closer) = closer {
+            closer
+        } else {
+            return false;
+        };
+        let prev = x.checked_sub(1).and_then(|x| self.char_at(x));
+        let next_is_free = next.map_or(true, |next| {
+            next.is_whitespace()
+                || self
+                    .document
+                    .pairs()
+                    .iter()
+                    .any(|(_, close)| *close == next)
+        });
+        let prev_is_word = prev.map_or(false, |prev| prev.is_alphanumeric() || prev == '_');
+        if !next_is_free || (closer == c && prev_is_word) {
+            return false;
+        }
+        let mut pair = c.to_string();
+        pair.push(closer);
+        self.document.insert_str(&self.cursor_position, &pair);
+        self.move_cursor(Key::Right);
+        true
+    }
+    fn backspace(&mut self) {
+        if self.cursor_position.x == 0 && self.cursor_position.y == 0 {
+            return;
+        }
+        self.move_cursor(Key::Left);
+        let x = self.cursor_position.x;
+        let is_empty_pair = self.config.auto_pairs
+            && match (self.char_at(x), self.char_at(x.saturating_add(1))) {
+                (Some(prev), Some(next)) => self.document.pairs().contains(&(prev, next)),
+                _ => false,
+            };
+        if is_empty_pair {
+            self.document.delete(&self.cursor_position);
+        }
+        self.document.delete(&self.cursor_position);
+    }
+    /// The rows an indent or outdent applies to: every line the selection
+    /// touches, or just the cursor's line.
+    fn selected_lines(&self) -> Range<usize> {
+        if let Some((start, end)) = self.selection_range() {
+            let last = if end.x == 0 {
+                end.y
+            } else {
+                end.y.saturating_add(1)
+            };
+            start.y..last
+        } else {
+            self.cursor_position.y..self.cursor_position.y.saturating_add(1)
+        }
+    }
+    fn shift_lines(&mut self, outdent: bool) {
+        for y in self.selected_lines() {
+            let count = if outdent {
+                self.document.outdent(y)
+            } else {
+                self.document.indent(y)
+            };
+            let positions = self
+                .selection
+                .iter_mut()
+                .chain(Some(&mut self.cursor_position));
+            for position in positions.filter(|position| position.y == y) {
+                position.x = if outdent {
+                    position.x.saturating_sub(count)
+                } else if position.x > 0 {
+                    position.x.saturating_add(count)
+                } else {
+                    0
+                };
+            }
+        }
+    }
+    /// The screen column of the cursor within its row, before scrolling.
+    fn cursor_render_x(&self) -> usize {
         let Position { x, y } = self.cursor_position;
+        self.document
+            .row(y)
+            .map_or(0, |row| row.render_x(x, self.document.indentation().width))
+    }
+    fn scroll(&mut self) {
+        let x = self.cursor_render_x();
+        let y = self.cursor_position.y;
         let width = self.terminal.size().width as usize;
         let height = self.terminal.size().height as usize;
-        let mut offset = &mut self.offset;
+        let offset = &mut self.offset;
         if y < offset.y {
             offset.y = y;
         } else if y >= offset.y.saturating_add(height) {
@@ -283,6 +1779,16 @@ impl Editor {
             }
             Key::Home => x = 0,
             Key::End => x = width,
+            Key::CtrlLeft => {
+                let position = self.document.previous_word(&Position { x, y });
+                x = position.x;
+                y = position.y;
+            }
+            Key::CtrlRight => {
+                let position = self.document.next_word(&Position { x, y });
+                x = position.x;
+                y = position.y;
+            }
             _ => (),
         }
         width = if let Some(row) = self.document.row(y) {
@@ -296,7 +1802,7 @@ impl Editor {
 
         self.cursor_position = Position { x, y }
     }
-    fn draw_welcome_message(&self) {
+    fn welcome_message(&self) -> String {
         let mut welcome_message = format!("Hecto editor -- version {}", VERSION);
         let width = self.terminal.size().width as usize;
         let len = welcome_message.len();
@@ -305,33 +1811,42 @@ impl Editor {
         let spaces = " ".repeat(padding.saturating_sub(1));
         welcome_message = format!("~{}{}", spaces, welcome_message);
         welcome_message.truncate(width);
-        println!("{}\r", welcome_message);
+        welcome_message
     }
-    pub fn draw_row(&self, row: &Row) {
+    fn render_row(&self, row: &Row, selected: &Range<usize>) -> String {
         let width = self.terminal.size().width as usize;
         let start = self.offset.x;
         let end = self.offset.x.saturating_add(width);
-        let row = row.render(start, end);
-        println!("{}\r", row)
+        row.render(start, end, selected, self.document.indentation().width)
+    }
+    /// The columns of row `y` covered by the selection.
+    fn selected_columns(&self, y: usize, row: &Row) -> Range<usize> {
+        if let Some((start, end)) = self.selection_range() {
+            if start.y <= y && y <= end.y {
+                let from = if y == start.y { start.x } else { 0 };
+                let to = if y == end.y { end.x } else { row.len() };
+                return from..to;
+            }
+        }
+        0..0
     }
     #[allow(clippy::integer_division, clippy::integer_arithmetic)]
-    fn draw_rows(&self) {
+    fn draw_rows(&mut self) {
         let height = self.terminal.size().height;
         for terminal_row in 0..height {
-            Terminal::clear_current_line();
-            if let Some(row) = self
-                .document
-                .row(self.offset.y.saturating_add(terminal_row as usize))
-            {
-                self.draw_row(row);
+            let y = self.offset.y.saturating_add(terminal_row as usize);
+            let line = if let Some(row) = self.document.row(y) {
+                self.render_row(row, &self.selected_columns(y, row))
             } else if self.document.is_empty() && terminal_row == height / 3 {
-                self.draw_welcome_message();
+                self.welcome_message()
             } else {
-                println!("~\r");
-            }
+                "~".to_string()
+            };
+            self.terminal.clear_current_line();
+            self.terminal.print(&format!("{}\r\n", line));
         }
     }
-    fn draw_status_bar(&self) {
+    fn draw_status_bar(&mut self) {
         let mut status;
         let width = self.terminal.size().width as usize;
         let modified_indicator = if self.document.is_dirty() {
@@ -345,16 +1860,44 @@ impl Editor {
             file_name = name.clone();
             file_name.truncate(20);
         }
+        if self.buffers.len() > 1 {
+            file_name = format!(
+                "[{}/{}] {}",
+                self.buffer_index.saturating_add(1),
+                self.buffers.len(),
+                file_name
+            );
+        }
+        let read_only_indicator = if self.document.is_read_only() {
+            " [RO]"
+        } else {
+            ""
+        };
+        let mode_indicator = match &self.vim {
+            Some(vim) if vim.pending.is_empty() => format!("{} | ", vim.mode.name()),
+            Some(vim) => format!("{} {} | ", vim.mode.name(), vim.pending),
+            None => String::new(),
+        };
         status = format!(
-            "{} - {} lines{}",
+            "{}{}{} - {} lines{}",
+            mode_indicator,
             file_name,
+            read_only_indicator,
             self.document.len(),
             modified_indicator
         );
 
+        let indentation = self.document.indentation();
+        let indentation = match indentation.style {
+            IndentStyle::Tabs => format!("tabs:{}", indentation.width),
+            IndentStyle::Spaces => format!("spaces:{}", indentation.width),
+        };
         let line_indicator = format!(
-            "{} | {}/{}",
+            "{} | {} | {} | {} | {}/{}",
             self.document.file_type(),
+            indentation,
+            self.document.line_ending().name(),
+            self.document.encoding().name(),
             self.cursor_position.y.saturating_add(1),
             self.document.len()
         );
@@ -363,22 +1906,36 @@ impl Editor {
         status.push_str(&" ".repeat(width.saturating_sub(len)));
         status = format!("{}{}", status, line_indicator);
         status.truncate(width);
-        Terminal::set_bg_color(STATUS_BG_COLOR);
-        Terminal::set_fg_color(STATUS_FG_COLOR);
-        println!("{}\r", status);
-        Terminal::reset_fg_color();
-        Terminal::reset_bg_color();
-    }
-    fn draw_message_bar(&self) {
-        Terminal::clear_current_line();
+        self.terminal.set_bg_color(STATUS_BG_COLOR);
+        self.terminal.set_fg_color(STATUS_FG_COLOR);
+        self.terminal.print(&format!("{}\r\n", status));
+        self.terminal.reset_fg_color();
+        self.terminal.reset_bg_color();
+    }
+    fn draw_message_bar(&mut self) {
+        self.terminal.clear_current_line();
         let message = &self.status_message;
-        if Instant::now() - message.time < Duration::new(5, 0) {
+        if message.is_shown() {
             let mut text = message.text.clone();
             text.truncate(self.terminal.size().width as usize);
-            print!("{}", text);
+            self.terminal.print(&text);
         }
     }
-    fn prompt<C>(&mut self, prompt: &str, mut callback: C) -> Result<Option<String>, std::io::Error>
+    fn prompt<C>(&mut self, prompt: &str, callback: C) -> Result<Option<String>, std::io::Error>
+    where
+        C: FnMut(&mut Self, Key, &String),
+    {
+        Ok(self
+            .prompt_input(prompt, callback)?
+            .filter(|result| !result.is_empty()))
+    }
+    /// Like `prompt`, but Enter with nothing typed gives an empty string, so
+    /// only Esc gives `None`.
+    fn prompt_input<C>(
+        &mut self,
+        prompt: &str,
+        mut callback: C,
+    ) -> Result<Option<String>, std::io::Error>
     where
         C: FnMut(&mut Self, Key, &String),
     {
@@ -386,32 +1943,468 @@ impl Editor {
         loop {
             self.status_message = StatusMessage::from(format!("{}{}", prompt, result));
             self.refresh_screen()?;
-            let key = Terminal::read_key()?;
+            let key = self.terminal.read_key()?;
             match key {
-                Key::Backspace => result.truncate(result.len().saturating_sub(1)),
+                Key::Backspace => {
+                    result.pop();
+                }
                 Key::Char('\n') => break,
                 Key::Char(c) => {
                     if !c.is_control() {
                         result.push(c);
                     }
                 }
+                Key::Paste(ref text) => {
+                    let line = text.lines().next().unwrap_or_default();
+                    result.extend(line.chars().filter(|c| !c.is_control()));
+                }
                 Key::Esc => {
-                    result.truncate(0);
-                    break;
+                    self.status_message = StatusMessage::from(String::new());
+                    return Ok(None);
                 }
                 _ => (),
             }
             callback(self, key, &result);
         }
         self.status_message = StatusMessage::from(String::new());
-        if result.is_empty() {
-            return Ok(None);
-        }
         Ok(Some(result))
     }
 }
 
-fn die(e: std::io::Error) {
-    Terminal::clear_screen();
-    panic!(e);
+/// The zero-based position for a 1-based line and column, clamped to
+/// `document`.
+fn start_position(document: &Document, line: Option<usize>, column: Option<usize>) -> Position {
+    let y = line
+        .unwrap_or(1)
+        .saturating_sub(1)
+        .min(document.len().saturating_sub(1));
+    let width = document.row(y).map_or(0, Row::len);
+    Position {
+        x: column.unwrap_or(1).saturating_sub(1).min(width),
+        y,
+    }
+}
+
+/// `a` and `b` in document order.
+fn ordered(a: Position, b: Position) -> (Position, Position) {
+    if (a.y, a.x) <= (b.y, b.x) {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+/// The scroll offset that puts row `y` in the middle of the screen.
+fn centered_offset(size: &Size, y: usize) -> Position {
+    #[allow(clippy::integer_division)]
+    let half = size.height as usize / 2;
+    Position {
+        x: 0,
+        y: y.saturating_sub(half),
+    }
+}
+
+/// Resolves a go-to-line target, relative to the zero-based `current` line,
+/// into a zero-based line, clamped to the document, and column.
+///
+/// Accepts `line`, `line:col`, `+N` and `-N` for relative jumps and `N%` for a
+/// percentage of the document.
+fn parse_goto(input: &str, current: usize, len: usize) -> Option<(usize, Option<usize>)> {
+    let input = input.trim();
+    let last_line = len.saturating_sub(1);
+    if let Some(percent) = input.strip_suffix('%') {
+        let percent: usize = percent.trim().parse().ok()?;
+        #[allow(clippy::integer_arithmetic, clippy::integer_division)]
+        let y = last_line.saturating_mul(percent.min(100)) / 100;
+        return Some((y, None));
+    }
+    let mut parts = input.splitn(2, ':');
+    let line = parts.next()?.trim();
+    let column = match parts.next() {
+        Some(column) => Some(column.trim().parse::<usize>().ok()?.saturating_sub(1)),
+        None => None,
+    };
+    let y = if let Some(lines) = line.strip_prefix('+') {
+        current.saturating_add(lines.parse().ok()?)
+    } else if let Some(lines) = line.strip_prefix('-') {
+        current.saturating_sub(lines.parse().ok()?)
+    } else {
+        line.parse::<usize>().ok()?.saturating_sub(1)
+    };
+    Some((y.min(last_line), column))
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+    use crate::args::FileArg;
+    use crate::headless::Headless;
+    use crate::tempdir::TempDir;
+
+    /// An editor on an empty buffer, drawing on a 40x10 headless terminal.
+    fn headless_editor() -> (Editor, Headless) {
+        headless_editor_with(Args::default(), Config::default(), 40)
+    }
+
+    /// An editor on a headless screen `width` columns wide and 10 lines
+    /// high.
+    fn headless_editor_with(args: Args, config: Config, width: u16) -> (Editor, Headless) {
+        let screen = Headless::new(width, 10);
+        let editor = Editor::with_backend(args, config, Box::new(screen.clone())).unwrap();
+        (editor, screen)
+    }
+
+    /// Arguments that open `file_name`.
+    fn file_args(file_name: &str) -> Args {
+        let file = FileArg {
+            path: file_name.to_string(),
+            line: None,
+            column: None,
+        };
+        Args {
+            files: vec![file],
+            ..Args::default()
+        }
+    }
+
+    /// Feeds the scripted keys to `editor` and draws the result.
+    fn run_keys(editor: &mut Editor, screen: &Headless) {
+        while screen.has_keys() {
+            let key = editor.terminal.read_key().unwrap();
+            editor.process_keypress(key).unwrap();
+        }
+        editor.refresh_screen().unwrap();
+    }
+
+    /// Whether `result` failed because the scripted keys ran out, which is
+    /// how a test stops the editor in the middle of a prompt to look at it.
+    fn ran_out_of_keys<T>(result: Result<T, std::io::Error>) -> bool {
+        matches!(result, Err(error) if error.kind() == ErrorKind::UnexpectedEof)
+    }
+
+    #[test]
+    fn test_typing_draws_text() {
+        let (mut editor, screen) = headless_editor();
+        screen.type_text("hello\nworld");
+        run_keys(&mut editor, &screen);
+        let lines = screen.lines();
+        assert_eq!(&lines[..3], &["hello", "world", "~"]);
+        assert!(lines[8].starts_with("[No Name] - 2 lines (modified)"));
+        assert_eq!(screen.cursor(), Position { x: 5, y: 1 });
+    }
+
+    #[test]
+    fn test_auto_pairs() {
+        let (mut editor, screen) = headless_editor();
+        // The closer typed over the inserted one is not doubled.
+        screen.type_text("f(x)");
+        run_keys(&mut editor, &screen);
+        assert_eq!(screen.lines()[0], "f(x)");
+        assert_eq!(screen.cursor(), Position { x: 4, y: 0 });
+
+        // Backspace inside an empty pair removes both halves.
+        screen.type_text("[");
+        run_keys(&mut editor, &screen);
+        assert_eq!(screen.lines()[0], "f(x)[]");
+        screen.push_keys(&[Key::Backspace]);
+        run_keys(&mut editor, &screen);
+        assert_eq!(screen.lines()[0], "f(x)");
+
+        // An opener typed over a selection wraps it.
+        screen.type_text("\nword");
+        screen.push_keys(&[Key::ShiftHome, Key::Char('{')]);
+        run_keys(&mut editor, &screen);
+        assert_eq!(&screen.lines()[..2], &["f(x)", "{word}"]);
+        assert_eq!(screen.cursor(), Position { x: 5, y: 1 });
+    }
+
+    #[test]
+    fn test_prompt_and_selection() {
+        let (mut editor, screen) = headless_editor();
+        screen.type_text("one\ntwo\nthree");
+        screen.push_keys(&[Key::Ctrl('g'), Key::Char('1'), Key::Char('\n')]);
+        screen.push_keys(&[Key::ShiftEnd, Key::Delete, Key::Ctrl('q')]);
+        run_keys(&mut editor, &screen);
+        let lines = screen.lines();
+        assert_eq!(&lines[..3], &["", "two", "three"]);
+        assert!(lines[9].starts_with("WARNING! File has unsaved changes."));
+    }
+
+    #[test]
+    fn test_mouse_selection() {
+        let (mut editor, screen) = headless_editor();
+        screen.type_text("hello world\nsecond line");
+        screen.push_keys(&[Key::Click(8, 1), Key::Click(8, 1), Key::Char('X')]);
+        screen.push_keys(&[Key::Click(1, 2), Key::Drag(8, 2), Key::Delete]);
+        run_keys(&mut editor, &screen);
+        assert_eq!(&screen.lines()[..2], &["hello X", "line"]);
+    }
+
+    #[test]
+    fn test_paste_is_inserted_verbatim() {
+        let (mut editor, screen) = headless_editor();
+        screen.push_keys(&[Key::Paste("fn a() {\n  x\n}".to_string())]);
+        run_keys(&mut editor, &screen);
+        assert_eq!(&screen.lines()[..3], &["fn a() {", "  x", "}"]);
+        assert_eq!(screen.cursor(), Position { x: 1, y: 2 });
+    }
+
+    #[test]
+    fn test_swap_recovery() {
+        let dir = TempDir::new("recovery");
+        let file_name = dir.join("notes.txt").to_string_lossy().to_string();
+        let open = |keys: &[Key]| {
+            fs::write(&file_name, "one\ntwo\n").unwrap();
+            swap::write(&swap::path(&file_name), "one\nchanged\n").unwrap();
+            let (mut editor, screen) =
+                headless_editor_with(file_args(&file_name), Config::default(), 40);
+            screen.push_keys(keys);
+            let result = editor.check_swaps();
+            (editor, screen, result)
+        };
+
+        // The diff stays up until the next answer, or here until the keys
+        // run out.
+        let (_, screen, result) = open(&[Key::Char('d')]);
+        assert!(ran_out_of_keys(result));
+        assert_eq!(&screen.lines()[..3], &["@@ line 2 @@", "-two", "+changed"]);
+        assert!(screen.lines()[9].starts_with("Found swap file"));
+
+        let (editor, _, result) = open(&[Key::Char('d'), Key::Char('r')]);
+        result.unwrap();
+        assert_eq!(editor.document.text(), "one\nchanged\n");
+        assert!(editor.document.is_dirty());
+
+        let (editor, _, result) = open(&[Key::Char('x')]);
+        result.unwrap();
+        assert_eq!(editor.document.text(), "one\ntwo\n");
+        assert!(!swap::path(&file_name).exists());
+    }
+
+    #[test]
+    fn test_external_change_while_dirty() {
+        let dir = TempDir::new("external");
+        let file_name = dir.write("notes.txt", "one\n");
+        let (mut editor, screen) =
+            headless_editor_with(file_args(&file_name), Config::default(), 100);
+        screen.type_text("x");
+        run_keys(&mut editor, &screen);
+
+        // Keeping the changes asks no more about the same change.
+        fs::write(&file_name, "one\ntwo\n").unwrap();
+        screen.type_text("n");
+        editor.check_disk_change().unwrap();
+        assert!(screen.lines()[9].ends_with("Reload it and lose your changes? (y/n)"));
+        editor.check_disk_change().unwrap();
+        assert_eq!(editor.document.text(), "xone\n");
+
+        // A further change asks again.
+        fs::write(&file_name, "one\ntwo\nthree\n").unwrap();
+        screen.type_text("y");
+        editor.check_disk_change().unwrap();
+        assert_eq!(editor.document.text(), "one\ntwo\nthree\n");
+        assert!(!editor.document.is_dirty());
+    }
+
+    #[test]
+    fn test_swap_written_while_idle() {
+        let dir = TempDir::new("idle");
+        let file_name = dir.write("notes.txt", "one\n");
+        let (mut editor, screen) =
+            headless_editor_with(file_args(&file_name), Config::default(), 40);
+        let swap_text = || swap::read(&swap::path(&file_name)).unwrap().unwrap().text;
+
+        // The first change is written at once, the next one only when the
+        // interval is over.
+        screen.type_text("a");
+        editor.wait_and_process().unwrap();
+        assert_eq!(swap_text(), "aone\n");
+        screen.type_text("b");
+        editor.wait_and_process().unwrap();
+        assert!(editor.swap_pending);
+        assert_eq!(swap_text(), "aone\n");
+
+        // No key comes, and the pending write happens once it is due.
+        editor.swap_written = editor
+            .swap_written
+            .and_then(|written| written.checked_sub(SWAP_INTERVAL));
+        editor.wait_and_process().unwrap();
+        assert!(!editor.swap_pending);
+        assert_eq!(swap_text(), "abone\n");
+    }
+
+    #[test]
+    fn test_swap_written_before_switching_buffers() {
+        let dir = TempDir::new("switch");
+        let first = dir.write("one.txt", "one\n");
+        let second = dir.write("two.txt", "two\n");
+        let mut args = file_args(&first);
+        args.files.extend(file_args(&second).files);
+        let (mut editor, screen) = headless_editor_with(args, Config::default(), 40);
+        screen.type_text("ab");
+        editor.wait_and_process().unwrap();
+        editor.wait_and_process().unwrap();
+        assert!(editor.swap_pending);
+
+        editor.next_buffer(true).unwrap();
+        let text = swap::read(&swap::path(&first)).unwrap().unwrap().text;
+        assert_eq!(text, "abone\n");
+    }
+
+    #[test]
+    fn test_save_over_external_change() {
+        let dir = TempDir::new("overwrite");
+        let file_name = dir.write("notes.txt", "one\n");
+        let (mut editor, screen) =
+            headless_editor_with(file_args(&file_name), Config::default(), 100);
+        screen.type_text("x");
+        run_keys(&mut editor, &screen);
+        fs::write(&file_name, "one\ntwo\n").unwrap();
+
+        // Failing to read the answer is an error, not a no.
+        assert!(ran_out_of_keys(editor.save()));
+        screen.type_text("y");
+        editor.save().unwrap();
+        assert_eq!(fs::read_to_string(&file_name).unwrap(), "xone\n");
+    }
+
+    #[test]
+    fn test_file_name_prompts() {
+        let (mut editor, screen) = headless_editor();
+        assert!(ran_out_of_keys(editor.save_as("Save as: ")));
+        assert!(ran_out_of_keys(editor.rename()));
+        assert!(ran_out_of_keys(editor.write_selection()));
+
+        screen.push_keys(&[Key::Esc]);
+        editor.write_selection().unwrap();
+        assert_eq!(editor.status_message.text, "Write aborted.");
+    }
+
+    #[test]
+    fn test_chords_and_help() {
+        let config = Config::parse("bind = Ctrl-K Ctrl-L toggle-line-ending").unwrap();
+        let (mut editor, screen) = headless_editor_with(Args::default(), config, 40);
+        screen.push_keys(&[Key::Ctrl('k')]);
+        run_keys(&mut editor, &screen);
+        assert_eq!(screen.lines()[9], "Ctrl-K-");
+        screen.push_keys(&[Key::Ctrl('l'), Key::Ctrl('k'), Key::Char('x')]);
+        run_keys(&mut editor, &screen);
+        assert_eq!(editor.document.line_ending(), LineEnding::CrLf);
+        assert_eq!(screen.lines()[9], "Ctrl-K x is not bound");
+        assert_eq!(editor.document.len(), 0);
+
+        // Help runs until a key closes it, or here until the keys run out.
+        screen.push_keys(&[Key::Down]);
+        assert!(ran_out_of_keys(editor.show_help()));
+        assert!(screen.lines()[2].starts_with("Ctrl-Q"));
+        assert_eq!(editor.document.len(), 0);
+    }
+
+    #[test]
+    fn test_command_palette() {
+        let (mut editor, screen) = headless_editor_with(Args::default(), Config::default(), 60);
+        screen.type_text("hi");
+        run_keys(&mut editor, &screen);
+
+        // The palette runs until Enter or Esc, or here until the keys run out.
+        // Backspace takes off whole characters.
+        screen.type_text("tgleé");
+        screen.push_keys(&[Key::Backspace]);
+        assert!(ran_out_of_keys(editor.command_palette()));
+        assert!(screen.lines()[0].starts_with("Alt-l"));
+        assert_eq!(screen.lines()[1], "~");
+        assert_eq!(screen.lines()[9], "Command (Up/Down to pick): tgle");
+        screen.push_keys(&[Key::Ctrl('p')]);
+        screen.type_text("tgle");
+        screen.push_keys(&[Key::Esc]);
+        run_keys(&mut editor, &screen);
+        assert_eq!(screen.lines()[0], "hi");
+        assert_eq!(editor.document.line_ending(), LineEnding::Lf);
+
+        screen.push_keys(&[Key::Ctrl('p')]);
+        screen.type_text("tgle\n");
+        run_keys(&mut editor, &screen);
+        assert_eq!(screen.lines()[0], "hi");
+        assert_eq!(editor.document.line_ending(), LineEnding::CrLf);
+
+        // With nothing typed, Enter runs the command picked with the arrows.
+        screen.push_keys(&[Key::Ctrl('p'), Key::Down, Key::Char('\n')]);
+        run_keys(&mut editor, &screen);
+        assert!(screen.lines()[9].starts_with("WARNING! File has unsaved changes."));
+    }
+
+    #[test]
+    fn test_modal_editing() {
+        let config = Config::parse("modal = true").unwrap();
+        let (mut editor, screen) = headless_editor_with(Args::default(), config, 40);
+        screen.type_text("ione two three\nsay(\"hi there\")");
+        screen.push_keys(&[Key::Esc]);
+        run_keys(&mut editor, &screen);
+        assert!(screen.lines()[8].starts_with("NORMAL | [No Name]"));
+        assert_eq!(screen.cursor(), Position { x: 14, y: 1 });
+
+        screen.type_text("0ci\"bye");
+        screen.push_keys(&[Key::Esc]);
+        screen.type_text("ggwdwyyjp2x");
+        run_keys(&mut editor, &screen);
+        assert_eq!(
+            &screen.lines()[..3],
+            &["one three", "say(\"bye\")", "e three"]
+        );
+        screen.type_text("d");
+        run_keys(&mut editor, &screen);
+        assert!(screen.lines()[8].starts_with("NORMAL d | "));
+        screen.type_text("kviwy0P");
+        run_keys(&mut editor, &screen);
+        assert_eq!(&screen.lines()[..2], &["oneone three", "~"]);
+    }
+
+    #[test]
+    fn test_huge_counts() {
+        let config = Config::parse("modal = true").unwrap();
+        let (mut editor, screen) = headless_editor_with(Args::default(), config, 40);
+        screen.type_text("ione two");
+        screen.push_keys(&[Key::Esc]);
+        screen.type_text("0999999999w");
+        run_keys(&mut editor, &screen);
+        assert_eq!(editor.cursor_position, Position { x: 6, y: 0 });
+
+        screen.type_text("yy99999999999p");
+        run_keys(&mut editor, &screen);
+        assert_eq!(editor.document.len(), vim::MAX_COUNT + 1);
+    }
+
+    #[test]
+    fn test_emacs_kill_ring() {
+        let config = Config::parse("keymap = emacs").unwrap();
+        let (mut editor, screen) = headless_editor_with(Args::default(), config, 40);
+        screen.type_text("one two\nthree");
+        screen.push_keys(&[Key::Ctrl('a'), Key::Null, Key::Alt('f'), Key::Alt('w')]);
+        screen.push_keys(&[
+            Key::Ctrl('p'),
+            Key::Ctrl('a'),
+            Key::Ctrl('k'),
+            Key::Ctrl('k'),
+        ]);
+        run_keys(&mut editor, &screen);
+        assert_eq!(&screen.lines()[..2], &["three", "~"]);
+        screen.push_keys(&[Key::Ctrl('y'), Key::Alt('y')]);
+        run_keys(&mut editor, &screen);
+        assert_eq!(&screen.lines()[..2], &["threethree", "~"]);
+        screen.push_keys(&[Key::Alt('y'), Key::Ctrl('e'), Key::Alt('y')]);
+        run_keys(&mut editor, &screen);
+        assert_eq!(&screen.lines()[..3], &["one two", "three", "~"]);
+        assert_eq!(screen.lines()[9], "The last command was not a yank");
+    }
+
+    #[test]
+    fn test_parse_goto() {
+        assert_eq!(parse_goto("42", 0, 100), Some((41, None)));
+        assert_eq!(parse_goto("42:7", 0, 100), Some((41, Some(6))));
+        assert_eq!(parse_goto("+5", 10, 100), Some((15, None)));
+        assert_eq!(parse_goto("-50", 10, 100), Some((0, None)));
+        assert_eq!(parse_goto("500", 0, 100), Some((99, None)));
+        assert_eq!(parse_goto("50%", 0, 101), Some((50, None)));
+        assert_eq!(parse_goto("x", 0, 100), None);
+    }
 }
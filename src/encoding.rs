@@ -0,0 +1,241 @@
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::io::{Error, ErrorKind};
+
+/// How many leading bytes are checked for NUL bytes to spot binary files.
+const BINARY_SAMPLE_BYTES: usize = 8000;
+const HEX_DUMP_WIDTH: usize = 16;
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+const UTF16_LE_BOM: &[u8] = b"\xff\xfe";
+const UTF16_BE_BOM: &[u8] = b"\xfe\xff";
+
+/// The encoding a file was read in, so it can be written back the same way.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Encoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Utf16LeNoBom,
+    Utf16BeNoBom,
+    /// UTF-8 with invalid bytes, which are shown as U+FFFD. Writing it gives
+    /// valid UTF-8, so the original file is not written back.
+    Utf8Lossy,
+    Latin1,
+    Binary,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Self::Utf8
+    }
+}
+
+impl Encoding {
+    /// Detects the encoding of `bytes` from its byte order mark or, lacking
+    /// one, by trying UTF-16 for text full of NUL bytes and then UTF-8. Text
+    /// that is mostly UTF-8 keeps its invalid bytes as U+FFFD, and anything
+    /// else is read as Latin-1, which can represent any byte sequence.
+    /// Returns `None` for binary data.
+    pub fn decode(bytes: &[u8]) -> Option<(Self, String)> {
+        if let Some(rest) = bytes.strip_prefix(UTF8_BOM) {
+            if let Ok(text) = String::from_utf8(rest.to_vec()) {
+                return Some((Self::Utf8Bom, text));
+            }
+        }
+        if let Some(rest) = bytes.strip_prefix(UTF16_LE_BOM) {
+            return decode_utf16(rest, u16::from_le_bytes).map(|text| (Self::Utf16Le, text));
+        }
+        if let Some(rest) = bytes.strip_prefix(UTF16_BE_BOM) {
+            return decode_utf16(rest, u16::from_be_bytes).map(|text| (Self::Utf16Be, text));
+        }
+        let sample = bytes.get(..BINARY_SAMPLE_BYTES).unwrap_or(bytes);
+        if sample.contains(&0) {
+            return decode_utf16_without_bom(bytes, sample);
+        }
+        if let Ok(text) = String::from_utf8(bytes.to_vec()) {
+            return Some((Self::Utf8, text));
+        }
+        let (multibyte, invalid) =
+            bytes
+                .utf8_chunks()
+                .fold((0_usize, 0_usize), |(multibyte, invalid), chunk| {
+                    let valid = chunk.valid().chars().filter(|c| !c.is_ascii()).count();
+                    let bad = usize::from(!chunk.invalid().is_empty());
+                    (multibyte.saturating_add(valid), invalid.saturating_add(bad))
+                });
+        if multibyte > 0 && multibyte >= invalid {
+            return Some((Self::Utf8Lossy, String::from_utf8_lossy(bytes).into_owned()));
+        }
+        Some((
+            Self::Latin1,
+            bytes.iter().map(|byte| char::from(*byte)).collect(),
+        ))
+    }
+    pub fn encode(self, text: &str) -> Result<Vec<u8>, Error> {
+        Ok(match self {
+            Self::Utf8 | Self::Utf8Lossy => text.as_bytes().to_vec(),
+            Self::Utf8Bom => [UTF8_BOM, text.as_bytes()].concat(),
+            Self::Utf16Le => encode_utf16(UTF16_LE_BOM, text, u16::to_le_bytes),
+            Self::Utf16Be => encode_utf16(UTF16_BE_BOM, text, u16::to_be_bytes),
+            Self::Utf16LeNoBom => encode_utf16(&[], text, u16::to_le_bytes),
+            Self::Utf16BeNoBom => encode_utf16(&[], text, u16::to_be_bytes),
+            Self::Latin1 => {
+                let mut bytes = Vec::with_capacity(text.len());
+                for c in text.chars() {
+                    let byte = u8::try_from(u32::from(c)).map_err(|_| {
+                        Error::new(
+                            ErrorKind::InvalidData,
+                            format!("U+{:04X} cannot be written as Latin-1", u32::from(c)),
+                        )
+                    })?;
+                    bytes.push(byte);
+                }
+                bytes
+            }
+            Self::Binary => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "binary files are shown as a hex dump and cannot be written",
+                ))
+            }
+        })
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Utf8 => "utf-8",
+            Self::Utf8Bom => "utf-8-bom",
+            Self::Utf16Le => "utf-16le",
+            Self::Utf16Be => "utf-16be",
+            Self::Utf16LeNoBom => "utf-16le-nobom",
+            Self::Utf16BeNoBom => "utf-16be-nobom",
+            Self::Utf8Lossy => "utf-8-invalid",
+            Self::Latin1 => "latin-1",
+            Self::Binary => "binary",
+        }
+    }
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Option<String> {
+    if bytes.len() % 2 != 0 {
+        return None;
+    }
+    #[allow(clippy::indexing_slicing)]
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16(&units).ok()
+}
+
+/// Reads `bytes`, which has NUL bytes in `sample`, as UTF-16 if the NULs
+/// are all on one side of the code units, as they are in text that is
+/// mostly ASCII. Anything else is binary.
+fn decode_utf16_without_bom(bytes: &[u8], sample: &[u8]) -> Option<(Encoding, String)> {
+    let nuls_at = |parity| {
+        sample
+            .iter()
+            .skip(parity)
+            .step_by(2)
+            .filter(|byte| **byte == 0)
+            .count()
+    };
+    let (even, odd) = (nuls_at(0), nuls_at(1));
+    #[allow(clippy::integer_arithmetic)]
+    let units = sample.len() / 2;
+    let (encoding, from_bytes): (Encoding, fn([u8; 2]) -> u16) =
+        if even == 0 && odd.saturating_mul(4) >= units {
+            (Encoding::Utf16LeNoBom, u16::from_le_bytes)
+        } else if odd == 0 && even.saturating_mul(4) >= units {
+            (Encoding::Utf16BeNoBom, u16::from_be_bytes)
+        } else {
+            return None;
+        };
+    let text = decode_utf16(bytes, from_bytes)?;
+    let is_text = text
+        .chars()
+        .all(|c| !c.is_control() || c.is_ascii_whitespace());
+    is_text.then(|| (encoding, text))
+}
+
+fn encode_utf16(bom: &[u8], text: &str, to_bytes: fn(u16) -> [u8; 2]) -> Vec<u8> {
+    let mut bytes = bom.to_vec();
+    for unit in text.encode_utf16() {
+        bytes.extend_from_slice(&to_bytes(unit));
+    }
+    bytes
+}
+
+/// Formats `bytes` as the lines of a classic hex dump: offset, hex bytes and
+/// the printable ASCII characters.
+pub fn hex_dump(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(HEX_DUMP_WIDTH)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut line = format!("{:08x} ", index.saturating_mul(HEX_DUMP_WIDTH));
+            for column in 0..HEX_DUMP_WIDTH {
+                if column % 8 == 0 {
+                    line.push(' ');
+                }
+                if let Some(byte) = chunk.get(column) {
+                    write!(line, "{:02x} ", byte).ok();
+                } else {
+                    line.push_str("   ");
+                }
+            }
+            line.push('|');
+            line.extend(chunk.iter().map(|byte| {
+                if byte.is_ascii_graphic() || *byte == b' ' {
+                    char::from(*byte)
+                } else {
+                    '.'
+                }
+            }));
+            line.push('|');
+            line
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let samples: [&[u8]; 4] = [
+            b"plain\n",
+            b"\xef\xbb\xbfbom\n",
+            b"caf\xe9\n",
+            b"\xff\xfeh\x00i\x00\n\x00",
+        ];
+        for bytes in &samples {
+            let (encoding, text) = Encoding::decode(bytes).unwrap();
+            assert_eq!(encoding.encode(&text).unwrap(), *bytes);
+        }
+        assert_eq!(Encoding::decode(b"caf\xe9").unwrap().1, "café");
+        assert_eq!(Encoding::decode(b"\x7fELF\x00\x01"), None);
+    }
+
+    #[test]
+    fn test_invalid_utf8_stays_utf8() {
+        let (encoding, text) = Encoding::decode(b"na\xc3\xafve caf\xc3\xa9 \xff\n").unwrap();
+        assert_eq!(encoding, Encoding::Utf8Lossy);
+        assert_eq!(text, "naïve café \u{fffd}\n");
+    }
+
+    #[test]
+    fn test_utf16_without_bom() {
+        let samples: [(&[u8], Encoding); 2] = [
+            (b"h\x00i\x00\n\x00", Encoding::Utf16LeNoBom),
+            (b"\x00h\x00i\x00\n", Encoding::Utf16BeNoBom),
+        ];
+        for (bytes, expected) in &samples {
+            let (encoding, text) = Encoding::decode(bytes).unwrap();
+            assert_eq!((encoding, text.as_str()), (*expected, "hi\n"));
+            assert_eq!(encoding.encode(&text).unwrap(), *bytes);
+        }
+        assert_eq!(Encoding::decode(b"\x00\x00\x01\x02"), None);
+    }
+}
@@ -1,6 +1,22 @@
 pub struct FileType {
     name: String,
     hl_opts: HighlightingOptions,
+    indent_openers: Vec<char>,
+    indent_closers: Vec<char>,
+    pairs: Vec<(char, char)>,
+    indentation: Indentation,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum IndentStyle {
+    Tabs,
+    Spaces,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Indentation {
+    pub style: IndentStyle,
+    pub width: usize,
 }
 
 #[derive(Default)]
@@ -19,6 +35,79 @@ impl Default for FileType {
         Self {
             name: String::from("No filetype"),
             hl_opts: HighlightingOptions::default(),
+            indent_openers: Vec::new(),
+            indent_closers: Vec::new(),
+            pairs: vec![('(', ')'), ('[', ']'), ('{', '}')],
+            indentation: Indentation::default(),
+        }
+    }
+}
+
+impl Default for Indentation {
+    fn default() -> Self {
+        Self {
+            style: IndentStyle::Spaces,
+            width: 4,
+        }
+    }
+}
+
+impl Indentation {
+    /// The whitespace making up one level of indentation.
+    pub fn unit(self) -> String {
+        match self.style {
+            IndentStyle::Tabs => String::from("\t"),
+            IndentStyle::Spaces => " ".repeat(self.width),
+        }
+    }
+    /// Guesses the indentation of `lines`, falling back to `default` for
+    /// anything the sample does not settle. Tabs win if more lines are
+    /// indented with tabs than with spaces; the space width is the most
+    /// common step between the indentation of consecutive lines.
+    pub fn detect<'a, I>(lines: I, default: Self) -> Self
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let mut tab_lines = 0_usize;
+        let mut space_lines = 0_usize;
+        let mut steps = [0_usize; 9];
+        let mut previous = 0;
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            if line.starts_with('\t') {
+                tab_lines = tab_lines.saturating_add(1);
+                continue;
+            }
+            let spaces = line
+                .len()
+                .saturating_sub(line.trim_start_matches(' ').len());
+            if spaces > 0 {
+                space_lines = space_lines.saturating_add(1);
+            }
+            if let Some(count) = steps.get_mut(spaces.saturating_sub(previous)) {
+                *count = count.saturating_add(1);
+            }
+            previous = spaces;
+        }
+        if tab_lines == 0 && space_lines == 0 {
+            return default;
+        }
+        if tab_lines > space_lines {
+            return Self {
+                style: IndentStyle::Tabs,
+                width: default.width,
+            };
+        }
+        let mut width = default.width;
+        let mut best = 0;
+        for (step, count) in steps.iter().enumerate().skip(2) {
+            if *count > best {
+                best = *count;
+                width = step;
+            }
+        }
+        Self {
+            style: IndentStyle::Spaces,
+            width,
         }
     }
 }
@@ -30,6 +119,19 @@ impl FileType {
     pub fn highlighting_options(&self) -> &HighlightingOptions {
         &self.hl_opts
     }
+    pub fn indent_openers(&self) -> &Vec<char> {
+        &self.indent_openers
+    }
+    pub fn indent_closers(&self) -> &Vec<char> {
+        &self.indent_closers
+    }
+    /// Opening and closing characters that are typed as a pair.
+    pub fn pairs(&self) -> &Vec<(char, char)> {
+        &self.pairs
+    }
+    pub fn indentation(&self) -> Indentation {
+        self.indentation
+    }
     pub fn from(file_name: &str) -> Self {
         if file_name.ends_with(".rs") {
             return Self {
@@ -110,6 +212,88 @@ impl FileType {
                         "f64".to_string(),
                     ],
                 },
+                indent_openers: vec!['{', '(', '['],
+                indent_closers: vec!['}', ')', ']'],
+                pairs: vec![('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')],
+                indentation: Indentation::default(),
+            };
+        }
+        if file_name.ends_with(".py") {
+            return Self {
+                name: String::from("Python"),
+                hl_opts: HighlightingOptions {
+                    numbers: true,
+                    strings: true,
+                    characters: false,
+                    comments: false,
+                    multiline_comments: false,
+                    primary_keywords: vec![
+                        "False".to_string(),
+                        "None".to_string(),
+                        "True".to_string(),
+                        "and".to_string(),
+                        "as".to_string(),
+                        "assert".to_string(),
+                        "async".to_string(),
+                        "await".to_string(),
+                        "break".to_string(),
+                        "class".to_string(),
+                        "continue".to_string(),
+                        "def".to_string(),
+                        "del".to_string(),
+                        "elif".to_string(),
+                        "else".to_string(),
+                        "except".to_string(),
+                        "finally".to_string(),
+                        "for".to_string(),
+                        "from".to_string(),
+                        "global".to_string(),
+                        "if".to_string(),
+                        "import".to_string(),
+                        "in".to_string(),
+                        "is".to_string(),
+                        "lambda".to_string(),
+                        "nonlocal".to_string(),
+                        "not".to_string(),
+                        "or".to_string(),
+                        "pass".to_string(),
+                        "raise".to_string(),
+                        "return".to_string(),
+                        "try".to_string(),
+                        "while".to_string(),
+                        "with".to_string(),
+                        "yield".to_string(),
+                    ],
+                    secondary_keywords: vec![
+                        "bool".to_string(),
+                        "bytes".to_string(),
+                        "dict".to_string(),
+                        "float".to_string(),
+                        "int".to_string(),
+                        "list".to_string(),
+                        "object".to_string(),
+                        "set".to_string(),
+                        "str".to_string(),
+                        "tuple".to_string(),
+                    ],
+                },
+                indent_openers: vec!['{', '(', '[', ':'],
+                indent_closers: vec!['}', ')', ']'],
+                pairs: vec![('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')],
+                indentation: Indentation::default(),
+            };
+        }
+        if file_name.ends_with("Makefile")
+            || file_name.ends_with("makefile")
+            || file_name.ends_with(".mk")
+        {
+            return Self {
+                name: String::from("Makefile"),
+                indentation: Indentation {
+                    style: IndentStyle::Tabs,
+                    width: 8,
+                },
+                ..Self::default()
             };
         }
         Self::default()
@@ -139,3 +323,29 @@ impl HighlightingOptions {
         self.multiline_comments
     }
 }
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_detect_indentation() {
+        let spaces = "fn a() {\n  if b {\n    c();\n  }\n}";
+        assert_eq!(
+            Indentation::detect(spaces.lines(), Indentation::default()),
+            Indentation {
+                style: IndentStyle::Spaces,
+                width: 2
+            }
+        );
+        let tabs = "fn a() {\n\tif b {\n\t\tc();\n\t}\n}";
+        assert_eq!(
+            Indentation::detect(tabs.lines(), Indentation::default()).style,
+            IndentStyle::Tabs
+        );
+        assert_eq!(
+            Indentation::detect("a\nb".lines(), Indentation::default()),
+            Indentation::default()
+        );
+    }
+}
@@ -0,0 +1,81 @@
+/// How well `query` matches `text`, or `None` if its characters do not all
+/// appear in `text` in order. Case and whitespace in `query` are ignored,
+/// and characters matched in a run or at the start of a word score higher.
+pub fn score(query: &str, text: &str) -> Option<usize> {
+    let text: Vec<char> = text.chars().map(|c| c.to_ascii_lowercase()).collect();
+    // The best score for the query so far with its last character matched at
+    // each position of `text`.
+    let mut best: Option<Vec<Option<usize>>> = None;
+    for wanted in query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_lowercase())
+    {
+        let scores = text
+            .iter()
+            .enumerate()
+            .map(|(index, &c)| {
+                if c != wanted {
+                    return None;
+                }
+                let word_start = index
+                    .checked_sub(1)
+                    .and_then(|before| text.get(before))
+                    .map_or(true, |before| !before.is_alphanumeric());
+                let score = if word_start { 3 } else { 1 };
+                match &best {
+                    None => Some(score),
+                    Some(previous) => previous
+                        .iter()
+                        .take(index)
+                        .enumerate()
+                        .filter_map(|(before, previous)| {
+                            let run = if before.saturating_add(1) == index {
+                                4
+                            } else {
+                                0
+                            };
+                            previous.map(|previous| previous.saturating_add(run))
+                        })
+                        .max()
+                        .map(|previous| previous.saturating_add(score)),
+                }
+            })
+            .collect();
+        best = Some(scores);
+    }
+    best.map_or(Some(0), |scores| scores.into_iter().flatten().max())
+}
+
+/// The indices of the items in `texts` that `query` matches, best first.
+/// Equally good matches keep their order.
+pub fn filter<'a, I>(query: &str, texts: I) -> Vec<usize>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut matches: Vec<(usize, usize)> = texts
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, text)| score(query, text).map(|score| (index, score)))
+        .collect();
+    matches.sort_by(|a, b| b.1.cmp(&a.1));
+    matches.into_iter().map(|(index, _)| index).collect()
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_matching() {
+        assert_eq!(score("", "save"), Some(0));
+        assert_eq!(score("sva", "save"), None);
+        assert!(score("SA", "save-as").is_some());
+        assert!(score("sa", "save-as") > score("sa", "select-all"));
+        assert!(score("pe", "page-end") > score("pe", "pager"));
+        let names = ["save", "save-as", "toggle-line-ending", "go-to-line"];
+        assert_eq!(filter("gtl", names.iter().copied()), vec![3]);
+        assert_eq!(filter("save as", names.iter().copied()), vec![1]);
+        assert_eq!(filter("", names.iter().copied()), vec![0, 1, 2, 3]);
+    }
+}
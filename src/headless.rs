@@ -0,0 +1,112 @@
+use crate::screen::Screen;
+use crate::Backend;
+use crate::Key;
+use crate::Position;
+use crate::Size;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+use termion::color;
+
+struct State {
+    keys: VecDeque<Key>,
+    screen: Screen,
+}
+
+/// An in-memory terminal for tests, which hands out scripted keys and keeps
+/// what is drawn on a `Screen`. Clones share the same state, so a test can
+/// keep one to look at while the editor owns another.
+#[derive(Clone)]
+pub struct Headless {
+    size: Size,
+    state: Rc<RefCell<State>>,
+}
+
+impl Headless {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            size: Size {
+                width,
+                height: height.saturating_sub(2),
+            },
+            state: Rc::new(RefCell::new(State {
+                keys: VecDeque::new(),
+                screen: Screen::new(width, height),
+            })),
+        }
+    }
+    pub fn push_keys(&self, keys: &[Key]) {
+        self.state.borrow_mut().keys.extend(keys.iter().cloned());
+    }
+    /// Scripts typing `text`, one `Key::Char` per character.
+    pub fn type_text(&self, text: &str) {
+        self.state
+            .borrow_mut()
+            .keys
+            .extend(text.chars().map(Key::Char));
+    }
+    pub fn has_keys(&self) -> bool {
+        !self.state.borrow().keys.is_empty()
+    }
+    /// The lines on the screen, without colors and trailing blanks.
+    pub fn lines(&self) -> Vec<String> {
+        let state = self.state.borrow();
+        (0..state.screen.height())
+            .map(|y| state.screen.line(y))
+            .collect()
+    }
+    pub fn cursor(&self) -> Position {
+        self.state.borrow().screen.cursor().clone()
+    }
+}
+
+impl Backend for Headless {
+    fn size(&self) -> &Size {
+        &self.size
+    }
+    fn clear_current_line(&mut self) {
+        self.state.borrow_mut().screen.clear_line();
+    }
+    fn cursor_position(&mut self, position: &Position) {
+        self.state.borrow_mut().screen.move_to(position);
+    }
+    fn cursor_hide(&mut self) {}
+    fn cursor_show(&mut self) {}
+    fn set_bg_color(&mut self, color: color::Rgb) {
+        self.state.borrow_mut().screen.set_bg(Some(color));
+    }
+    fn reset_bg_color(&mut self) {
+        self.state.borrow_mut().screen.set_bg(None);
+    }
+    fn set_fg_color(&mut self, color: color::Rgb) {
+        self.state.borrow_mut().screen.set_fg(Some(color));
+    }
+    fn reset_fg_color(&mut self) {
+        self.state.borrow_mut().screen.set_fg(None);
+    }
+    fn print(&mut self, text: &str) {
+        self.state.borrow_mut().screen.print(text);
+    }
+    fn flush(&mut self) -> Result<(), io::Error> {
+        Ok(())
+    }
+    fn read_key(&mut self) -> Result<Key, io::Error> {
+        self.state
+            .borrow_mut()
+            .keys
+            .pop_front()
+            .ok_or_else(|| io::Error::new(ErrorKind::UnexpectedEof, "no more scripted keys"))
+    }
+    /// Hands out the next scripted key or, once they have run out, waits
+    /// `timeout` as a terminal with no input would.
+    fn poll_key(&mut self, timeout: Duration) -> Result<Option<Key>, io::Error> {
+        let key = self.state.borrow_mut().keys.pop_front();
+        if key.is_none() {
+            thread::sleep(timeout);
+        }
+        Ok(key)
+    }
+}
@@ -0,0 +1,264 @@
+use std::fmt;
+use std::io::{self, Read};
+use termion::event::{self, Event, MouseButton, MouseEvent};
+
+/// What terminals in bracketed paste mode send around pasted text.
+pub const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// A key press as seen by the editor.
+///
+/// This mirrors `termion::event::Key`, but also covers the modified keys
+/// termion 1.5 hands out as unsupported escape sequences, the mouse and
+/// pasted text.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Key {
+    Backspace,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Delete,
+    Insert,
+    F(u8),
+    Char(char),
+    Alt(char),
+    Ctrl(char),
+    Null,
+    Esc,
+    ShiftLeft,
+    ShiftRight,
+    ShiftUp,
+    ShiftDown,
+    ShiftHome,
+    ShiftEnd,
+    BackTab,
+    CtrlLeft,
+    CtrlRight,
+    CtrlShiftLeft,
+    CtrlShiftRight,
+    AltBackspace,
+    CtrlDelete,
+    /// A left click at a 1-based terminal column and row.
+    Click(u16, u16),
+    /// Moving the mouse to a 1-based terminal column and row with a button
+    /// held.
+    Drag(u16, u16),
+    WheelUp,
+    WheelDown,
+    /// Text pasted in bracketed paste mode, with `\n` line breaks.
+    Paste(String),
+}
+
+impl Key {
+    pub fn from_event(event: Event) -> Option<Self> {
+        match event {
+            Event::Key(key) => Self::from_termion(key),
+            Event::Unsupported(sequence) => Self::from_sequence(&sequence),
+            Event::Mouse(mouse) => Self::from_mouse(mouse),
+        }
+    }
+    fn from_mouse(mouse: MouseEvent) -> Option<Self> {
+        match mouse {
+            MouseEvent::Press(MouseButton::Left, column, row) => Some(Self::Click(column, row)),
+            MouseEvent::Press(MouseButton::WheelUp, ..) => Some(Self::WheelUp),
+            MouseEvent::Press(MouseButton::WheelDown, ..) => Some(Self::WheelDown),
+            MouseEvent::Hold(column, row) => Some(Self::Drag(column, row)),
+            _ => None,
+        }
+    }
+    fn from_termion(key: event::Key) -> Option<Self> {
+        Some(match key {
+            // Many terminals send ^H for a plain Backspace.
+            event::Key::Backspace | event::Key::Ctrl('h') => Self::Backspace,
+            event::Key::Left => Self::Left,
+            event::Key::Right => Self::Right,
+            event::Key::Up => Self::Up,
+            event::Key::Down => Self::Down,
+            event::Key::Home => Self::Home,
+            event::Key::End => Self::End,
+            event::Key::PageUp => Self::PageUp,
+            event::Key::PageDown => Self::PageDown,
+            event::Key::Delete => Self::Delete,
+            event::Key::Insert => Self::Insert,
+            event::Key::F(n) => Self::F(n),
+            event::Key::Char(c) => Self::Char(c),
+            event::Key::Alt('\x7f') => Self::AltBackspace,
+            event::Key::Alt(c) => Self::Alt(c),
+            event::Key::Ctrl(c) => Self::Ctrl(c),
+            event::Key::Null => Self::Null,
+            event::Key::Esc => Self::Esc,
+            _ => return None,
+        })
+    }
+    fn from_sequence(sequence: &[u8]) -> Option<Self> {
+        Some(match sequence {
+            b"\x1b[1;2D" | b"\x1b[d" => Self::ShiftLeft,
+            b"\x1b[1;2C" | b"\x1b[c" => Self::ShiftRight,
+            b"\x1b[1;2A" | b"\x1b[a" => Self::ShiftUp,
+            b"\x1b[1;2B" | b"\x1b[b" => Self::ShiftDown,
+            b"\x1b[1;2H" => Self::ShiftHome,
+            b"\x1b[1;2F" => Self::ShiftEnd,
+            b"\x1b[Z" => Self::BackTab,
+            b"\x1b[1;5D" | b"\x1bOd" => Self::CtrlLeft,
+            b"\x1b[1;5C" | b"\x1bOc" => Self::CtrlRight,
+            b"\x1b[1;6D" => Self::CtrlShiftLeft,
+            b"\x1b[1;6C" => Self::CtrlShiftRight,
+            b"\x1b[3;5~" | b"\x1b[3^" => Self::CtrlDelete,
+            _ => return None,
+        })
+    }
+    /// The plain movement key behind a shifted one.
+    pub fn unshifted(&self) -> Option<Self> {
+        Some(match self {
+            Self::ShiftLeft => Self::Left,
+            Self::ShiftRight => Self::Right,
+            Self::ShiftUp => Self::Up,
+            Self::ShiftDown => Self::Down,
+            Self::ShiftHome => Self::Home,
+            Self::ShiftEnd => Self::End,
+            Self::CtrlShiftLeft => Self::CtrlLeft,
+            Self::CtrlShiftRight => Self::CtrlRight,
+            _ => return None,
+        })
+    }
+}
+
+/// The names of the special keys, as written in the config file.
+const KEY_NAMES: &[(Key, &str)] = &[
+    (Key::Backspace, "Backspace"),
+    (Key::Left, "Left"),
+    (Key::Right, "Right"),
+    (Key::Up, "Up"),
+    (Key::Down, "Down"),
+    (Key::Home, "Home"),
+    (Key::End, "End"),
+    (Key::PageUp, "PageUp"),
+    (Key::PageDown, "PageDown"),
+    (Key::Delete, "Delete"),
+    (Key::Insert, "Insert"),
+    (Key::Null, "Ctrl-Space"),
+    (Key::Esc, "Esc"),
+    (Key::Char('\n'), "Enter"),
+    (Key::Char('\t'), "Tab"),
+    (Key::Char(' '), "Space"),
+    (Key::ShiftLeft, "Shift-Left"),
+    (Key::ShiftRight, "Shift-Right"),
+    (Key::ShiftUp, "Shift-Up"),
+    (Key::ShiftDown, "Shift-Down"),
+    (Key::ShiftHome, "Shift-Home"),
+    (Key::ShiftEnd, "Shift-End"),
+    (Key::BackTab, "Shift-Tab"),
+    (Key::CtrlLeft, "Ctrl-Left"),
+    (Key::CtrlRight, "Ctrl-Right"),
+    (Key::CtrlShiftLeft, "Ctrl-Shift-Left"),
+    (Key::CtrlShiftRight, "Ctrl-Shift-Right"),
+    (Key::AltBackspace, "Alt-Backspace"),
+    (Key::CtrlDelete, "Ctrl-Delete"),
+    (Key::WheelUp, "WheelUp"),
+    (Key::WheelDown, "WheelDown"),
+];
+
+impl Key {
+    /// The key with the given name: a special key such as `PageUp` or
+    /// `Shift-Tab`, `F1` to `F12`, a single character, or `Ctrl-` or `Alt-`
+    /// followed by a character.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some((key, _)) = KEY_NAMES
+            .iter()
+            .find(|(_, key_name)| key_name.eq_ignore_ascii_case(name))
+        {
+            return Some(key.clone());
+        }
+        let single = |text: &str| {
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ => None,
+            }
+        };
+        if let Some(rest) = name.strip_prefix("Ctrl-") {
+            return single(rest).map(|c| Self::Ctrl(c.to_ascii_lowercase()));
+        }
+        if let Some(rest) = name.strip_prefix("Alt-") {
+            return single(rest).map(Self::Alt);
+        }
+        if let Some(number) = name.strip_prefix('F').and_then(|n| n.parse().ok()) {
+            return Some(Self::F(number));
+        }
+        single(name).map(Self::Char)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((_, name)) = KEY_NAMES.iter().find(|(key, _)| key == self) {
+            return f.write_str(name);
+        }
+        match self {
+            Self::F(number) => write!(f, "F{}", number),
+            Self::Char(c) => write!(f, "{}", c),
+            Self::Alt(c) => write!(f, "Alt-{}", c),
+            Self::Ctrl(c) => write!(f, "Ctrl-{}", c.to_ascii_uppercase()),
+            Self::Click(..) => f.write_str("Click"),
+            Self::Drag(..) => f.write_str("Drag"),
+            Self::Paste(_) => f.write_str("Paste"),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+/// Reads pasted text from `input` up to the end of the paste, after
+/// `PASTE_START` was read. Terminals send line breaks as `\r`.
+pub fn read_paste<R: Read>(input: &mut R) -> Result<String, io::Error> {
+    let mut bytes = Vec::new();
+    for byte in input.bytes() {
+        bytes.push(byte?);
+        if bytes.ends_with(PASTE_END) {
+            bytes.truncate(bytes.len().saturating_sub(PASTE_END.len()));
+            break;
+        }
+    }
+    Ok(String::from_utf8_lossy(&bytes)
+        .replace("\r\n", "\n")
+        .replace('\r', "\n"))
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_read_paste() {
+        let mut input: &[u8] = b"one\r\ttwo\r\n\x1b[201~rest";
+        assert_eq!(read_paste(&mut input).unwrap(), "one\n\ttwo\n");
+        assert_eq!(input, b"rest");
+    }
+
+    #[test]
+    fn test_backspace_keys() {
+        let key = Key::from_termion;
+        assert_eq!(key(event::Key::Ctrl('h')), Some(Key::Backspace));
+        assert_eq!(key(event::Key::Backspace), Some(Key::Backspace));
+        assert_eq!(key(event::Key::Alt('\x7f')), Some(Key::AltBackspace));
+        assert_eq!(Key::from_name("Alt-Backspace"), Some(Key::AltBackspace));
+        assert_eq!(Key::AltBackspace.to_string(), "Alt-Backspace");
+    }
+
+    #[test]
+    fn test_key_names() {
+        assert_eq!(Key::from_name("Ctrl-K"), Some(Key::Ctrl('k')));
+        assert_eq!(Key::from_name("Alt-x"), Some(Key::Alt('x')));
+        assert_eq!(Key::from_name("shift-tab"), Some(Key::BackTab));
+        assert_eq!(Key::from_name("F1"), Some(Key::F(1)));
+        assert_eq!(Key::from_name("q"), Some(Key::Char('q')));
+        assert_eq!(Key::from_name("Ctrl-Nope"), None);
+        for name in &["Ctrl-Q", "Alt-l", "Enter", "Ctrl-Shift-Left", "F12", "x"] {
+            assert_eq!(Key::from_name(name).unwrap().to_string(), *name);
+        }
+    }
+}
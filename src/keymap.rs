@@ -0,0 +1,377 @@
+use crate::Key;
+
+/// Something the editor can be told to do, by a key binding or by name.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Command {
+    Help,
+    Palette,
+    Quit,
+    Save,
+    SaveAs,
+    Rename,
+    WriteSelection,
+    Find,
+    GoToLine,
+    NextBuffer,
+    PreviousBuffer,
+    ToggleLineEnding,
+    Indent,
+    Outdent,
+    DeleteForward,
+    DeleteBackward,
+    DeleteWordForward,
+    DeleteWordBackward,
+    ClearSelection,
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    MoveWordLeft,
+    MoveWordRight,
+    MoveLineStart,
+    MoveLineEnd,
+    PageUp,
+    PageDown,
+    SelectUp,
+    SelectDown,
+    SelectLeft,
+    SelectRight,
+    SelectWordLeft,
+    SelectWordRight,
+    SelectLineStart,
+    SelectLineEnd,
+    SetMark,
+    KillLine,
+    KillRegion,
+    CopyRegion,
+    Yank,
+    YankPop,
+}
+
+impl Command {
+    pub const ALL: &'static [Self] = &[
+        Self::Help,
+        Self::Palette,
+        Self::Quit,
+        Self::Save,
+        Self::SaveAs,
+        Self::Rename,
+        Self::WriteSelection,
+        Self::Find,
+        Self::GoToLine,
+        Self::NextBuffer,
+        Self::PreviousBuffer,
+        Self::ToggleLineEnding,
+        Self::Indent,
+        Self::Outdent,
+        Self::DeleteForward,
+        Self::DeleteBackward,
+        Self::DeleteWordForward,
+        Self::DeleteWordBackward,
+        Self::ClearSelection,
+        Self::MoveUp,
+        Self::MoveDown,
+        Self::MoveLeft,
+        Self::MoveRight,
+        Self::MoveWordLeft,
+        Self::MoveWordRight,
+        Self::MoveLineStart,
+        Self::MoveLineEnd,
+        Self::PageUp,
+        Self::PageDown,
+        Self::SelectUp,
+        Self::SelectDown,
+        Self::SelectLeft,
+        Self::SelectRight,
+        Self::SelectWordLeft,
+        Self::SelectWordRight,
+        Self::SelectLineStart,
+        Self::SelectLineEnd,
+        Self::SetMark,
+        Self::KillLine,
+        Self::KillRegion,
+        Self::CopyRegion,
+        Self::Yank,
+        Self::YankPop,
+    ];
+    /// The name the command goes by in the config file, and what it does.
+    fn info(self) -> (&'static str, &'static str) {
+        match self {
+            Self::Help => ("help", "Show the key bindings"),
+            Self::Palette => ("command-palette", "Find a command by name and run it"),
+            Self::Quit => ("quit", "Quit hecto"),
+            Self::Save => ("save", "Save the file"),
+            Self::SaveAs => ("save-as", "Save to a new file and edit that"),
+            Self::Rename => ("rename", "Rename the file"),
+            Self::WriteSelection => ("write-selection", "Write the selected lines to a file"),
+            Self::Find => ("find", "Search the file"),
+            Self::GoToLine => ("go-to-line", "Go to a line and column"),
+            Self::NextBuffer => ("next-buffer", "Switch to the next file"),
+            Self::PreviousBuffer => ("previous-buffer", "Switch to the previous file"),
+            Self::ToggleLineEnding => ("toggle-line-ending", "Switch between LF and CRLF"),
+            Self::Indent => ("indent", "Indent the selected lines, or insert a tab"),
+            Self::Outdent => ("outdent", "Outdent the current or selected lines"),
+            Self::DeleteForward => ("delete-forward", "Delete the character under the cursor"),
+            Self::DeleteBackward => ("delete-backward", "Delete the character before the cursor"),
+            Self::DeleteWordForward => ("delete-word-forward", "Delete to the end of the word"),
+            Self::DeleteWordBackward => ("delete-word-backward", "Delete to the start of the word"),
+            Self::ClearSelection => ("clear-selection", "Clear the selection"),
+            Self::MoveUp => ("move-up", "Move up a line"),
+            Self::MoveDown => ("move-down", "Move down a line"),
+            Self::MoveLeft => ("move-left", "Move left a character"),
+            Self::MoveRight => ("move-right", "Move right a character"),
+            Self::MoveWordLeft => ("move-word-left", "Move to the previous word"),
+            Self::MoveWordRight => ("move-word-right", "Move to the next word"),
+            Self::MoveLineStart => ("move-line-start", "Move to the start of the line"),
+            Self::MoveLineEnd => ("move-line-end", "Move to the end of the line"),
+            Self::PageUp => ("page-up", "Move up a screen"),
+            Self::PageDown => ("page-down", "Move down a screen"),
+            Self::SelectUp => ("select-up", "Select up a line"),
+            Self::SelectDown => ("select-down", "Select down a line"),
+            Self::SelectLeft => ("select-left", "Select left a character"),
+            Self::SelectRight => ("select-right", "Select right a character"),
+            Self::SelectWordLeft => ("select-word-left", "Select to the previous word"),
+            Self::SelectWordRight => ("select-word-right", "Select to the next word"),
+            Self::SelectLineStart => ("select-line-start", "Select to the start of the line"),
+            Self::SelectLineEnd => ("select-line-end", "Select to the end of the line"),
+            Self::SetMark => ("set-mark", "Start a region that moving extends"),
+            Self::KillLine => ("kill-line", "Kill to the end of the line"),
+            Self::KillRegion => ("kill-region", "Kill the region"),
+            Self::CopyRegion => ("copy-region", "Add the region to the kill ring"),
+            Self::Yank => ("yank", "Insert the last kill"),
+            Self::YankPop => ("yank-pop", "Replace the yanked text with the kill before"),
+        }
+    }
+    pub fn name(self) -> &'static str {
+        self.info().0
+    }
+    pub fn description(self) -> &'static str {
+        self.info().1
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|command| command.name() == name)
+    }
+    /// Whether the command changes the document, which read-only documents
+    /// refuse.
+    pub fn is_edit(self) -> bool {
+        matches!(
+            self,
+            Self::ToggleLineEnding
+                | Self::Indent
+                | Self::Outdent
+                | Self::DeleteForward
+                | Self::DeleteBackward
+                | Self::DeleteWordForward
+                | Self::DeleteWordBackward
+                | Self::KillLine
+                | Self::KillRegion
+                | Self::Yank
+                | Self::YankPop
+        )
+    }
+}
+
+/// What a sequence of keys means in a keymap.
+#[derive(PartialEq, Debug)]
+pub enum Binding {
+    Command(Command),
+    /// The start of a longer binding.
+    Prefix,
+    Unbound,
+}
+
+/// Which key sequences run which commands. A sequence of more than one key
+/// is a chord, like `Ctrl-K Ctrl-C`.
+pub struct Keymap {
+    bindings: Vec<(Vec<Key>, Command)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Self {
+            bindings: Vec::new(),
+        };
+        let defaults = [
+            (Key::F(1), Command::Help),
+            (Key::Ctrl('p'), Command::Palette),
+            (Key::Ctrl('q'), Command::Quit),
+            (Key::Ctrl('s'), Command::Save),
+            (Key::Alt('s'), Command::SaveAs),
+            (Key::Alt('r'), Command::Rename),
+            (Key::Alt('w'), Command::WriteSelection),
+            (Key::Ctrl('f'), Command::Find),
+            (Key::Ctrl('g'), Command::GoToLine),
+            (Key::Alt('n'), Command::NextBuffer),
+            (Key::Alt('p'), Command::PreviousBuffer),
+            (Key::Alt('l'), Command::ToggleLineEnding),
+            (Key::Char('\t'), Command::Indent),
+            (Key::BackTab, Command::Outdent),
+            (Key::Delete, Command::DeleteForward),
+            (Key::Backspace, Command::DeleteBackward),
+            (Key::CtrlDelete, Command::DeleteWordForward),
+            (Key::AltBackspace, Command::DeleteWordBackward),
+            (Key::Esc, Command::ClearSelection),
+            (Key::Up, Command::MoveUp),
+            (Key::Down, Command::MoveDown),
+            (Key::Left, Command::MoveLeft),
+            (Key::Right, Command::MoveRight),
+            (Key::CtrlLeft, Command::MoveWordLeft),
+            (Key::CtrlRight, Command::MoveWordRight),
+            (Key::Home, Command::MoveLineStart),
+            (Key::End, Command::MoveLineEnd),
+            (Key::PageUp, Command::PageUp),
+            (Key::PageDown, Command::PageDown),
+            (Key::ShiftUp, Command::SelectUp),
+            (Key::ShiftDown, Command::SelectDown),
+            (Key::ShiftLeft, Command::SelectLeft),
+            (Key::ShiftRight, Command::SelectRight),
+            (Key::CtrlShiftLeft, Command::SelectWordLeft),
+            (Key::CtrlShiftRight, Command::SelectWordRight),
+            (Key::ShiftHome, Command::SelectLineStart),
+            (Key::ShiftEnd, Command::SelectLineEnd),
+        ];
+        for (key, command) in defaults.iter().cloned() {
+            keymap.bind(vec![key], command);
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    /// The default bindings with emacs keys on top.
+    pub fn emacs() -> Self {
+        let mut keymap = Self::default();
+        let bindings: &[(&[Key], Command)] = &[
+            (&[Key::Ctrl('f')], Command::MoveRight),
+            (&[Key::Ctrl('b')], Command::MoveLeft),
+            (&[Key::Ctrl('n')], Command::MoveDown),
+            (&[Key::Ctrl('p')], Command::MoveUp),
+            (&[Key::Alt('x')], Command::Palette),
+            (&[Key::Ctrl('a')], Command::MoveLineStart),
+            (&[Key::Ctrl('e')], Command::MoveLineEnd),
+            (&[Key::Alt('f')], Command::MoveWordRight),
+            (&[Key::Alt('b')], Command::MoveWordLeft),
+            (&[Key::Ctrl('v')], Command::PageDown),
+            (&[Key::Alt('v')], Command::PageUp),
+            (&[Key::Ctrl('d')], Command::DeleteForward),
+            (&[Key::Alt('d')], Command::DeleteWordForward),
+            (&[Key::Null], Command::SetMark),
+            (&[Key::Ctrl('g')], Command::ClearSelection),
+            (&[Key::Ctrl('k')], Command::KillLine),
+            (&[Key::Ctrl('w')], Command::KillRegion),
+            (&[Key::Alt('w')], Command::CopyRegion),
+            (&[Key::Ctrl('y')], Command::Yank),
+            (&[Key::Alt('y')], Command::YankPop),
+            (&[Key::Ctrl('s')], Command::Find),
+            (&[Key::Alt('g'), Key::Alt('g')], Command::GoToLine),
+            (&[Key::Alt('g'), Key::Char('g')], Command::GoToLine),
+            (&[Key::Ctrl('x'), Key::Ctrl('s')], Command::Save),
+            (&[Key::Ctrl('x'), Key::Ctrl('w')], Command::SaveAs),
+            (&[Key::Ctrl('x'), Key::Ctrl('c')], Command::Quit),
+            (&[Key::Ctrl('x'), Key::Right], Command::NextBuffer),
+            (&[Key::Ctrl('x'), Key::Left], Command::PreviousBuffer),
+        ];
+        for (keys, command) in bindings {
+            keymap.bind(keys.to_vec(), *command);
+        }
+        keymap
+    }
+    /// Binds `keys` to `command`, replacing any binding that `keys` starts
+    /// with or that starts with `keys`.
+    pub fn bind(&mut self, keys: Vec<Key>, command: Command) {
+        self.unbind(&keys);
+        self.bindings.push((keys, command));
+    }
+    pub fn unbind(&mut self, keys: &[Key]) {
+        self.bindings
+            .retain(|(bound, _)| !bound.starts_with(keys) && !keys.starts_with(bound));
+    }
+    pub fn lookup(&self, keys: &[Key]) -> Binding {
+        let mut binding = Binding::Unbound;
+        for (bound, command) in &self.bindings {
+            if bound.as_slice() == keys {
+                return Binding::Command(*command);
+            }
+            if bound.starts_with(keys) {
+                binding = Binding::Prefix;
+            }
+        }
+        binding
+    }
+    /// The key sequences bound to `command`, in the order they were bound.
+    pub fn keys_for(&self, command: Command) -> impl Iterator<Item = &[Key]> {
+        self.bindings
+            .iter()
+            .filter(move |(_, bound)| *bound == command)
+            .map(|(keys, _)| keys.as_slice())
+    }
+    /// The first binding of `command` as it is written, or the command's
+    /// name if it has none.
+    pub fn hint(&self, command: Command) -> String {
+        self.keys_for(command)
+            .next()
+            .map_or_else(|| command.name().to_string(), format_keys)
+    }
+    /// A line for every command, with its bindings and what it does.
+    pub fn help_lines(&self) -> Vec<String> {
+        Command::ALL
+            .iter()
+            .map(|command| self.help_line(*command))
+            .collect()
+    }
+    /// `command`'s bindings, name and what it does, in aligned columns.
+    pub fn help_line(&self, command: Command) -> String {
+        let keys: Vec<String> = self.keys_for(command).map(format_keys).collect();
+        format!(
+            "{:<20} {:<22} {}",
+            keys.join(", "),
+            command.name(),
+            command.description()
+        )
+    }
+}
+
+/// Parses a key sequence written as key names separated by spaces, such as
+/// `Ctrl-K Ctrl-C`.
+pub fn parse_keys(text: &str) -> Result<Vec<Key>, String> {
+    let keys = text
+        .split_whitespace()
+        .map(|name| Key::from_name(name).ok_or_else(|| format!("unknown key `{}`", name)))
+        .collect::<Result<Vec<Key>, String>>()?;
+    if keys.is_empty() {
+        return Err("expected a key".to_string());
+    }
+    Ok(keys)
+}
+
+pub fn format_keys(keys: &[Key]) -> String {
+    let names: Vec<String> = keys.iter().map(Key::to_string).collect();
+    names.join(" ")
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_chords() {
+        let mut keymap = Keymap::default();
+        let chord = parse_keys("Ctrl-K Ctrl-C").unwrap();
+        keymap.bind(chord.clone(), Command::Save);
+        assert_eq!(keymap.lookup(&chord[..1]), Binding::Prefix);
+        assert_eq!(keymap.lookup(&chord), Binding::Command(Command::Save));
+        assert_eq!(keymap.lookup(&[Key::Char('x')]), Binding::Unbound);
+        assert_eq!(keymap.hint(Command::Save), "Ctrl-S");
+        keymap.bind(vec![Key::Ctrl('k')], Command::Quit);
+        assert_eq!(keymap.lookup(&chord), Binding::Unbound);
+        keymap.unbind(&[Key::Ctrl('s')]);
+        assert_eq!(keymap.hint(Command::Save), "save");
+        assert!(parse_keys("Ctrl-K Hyper-X").is_err());
+        for command in Command::ALL {
+            assert_eq!(Command::from_name(command.name()), Some(*command));
+        }
+    }
+}
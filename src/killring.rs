@@ -0,0 +1,65 @@
+/// How many kills are kept.
+const MAX_KILLS: usize = 60;
+
+/// Killed text, for yanking back. The newest kill is yanked first, and
+/// yanking again cycles through older ones.
+#[derive(Default)]
+pub struct KillRing {
+    kills: Vec<String>,
+    /// How many kills back from the newest the last yank was.
+    yank_depth: usize,
+}
+
+impl KillRing {
+    /// Adds a kill, or adds `text` to the newest kill if `append` is set, as
+    /// for kills in a row.
+    pub fn kill(&mut self, text: String, append: bool) {
+        match self.kills.last_mut() {
+            Some(last) if append => last.push_str(&text),
+            _ => {
+                self.kills.push(text);
+                if self.kills.len() > MAX_KILLS {
+                    self.kills.remove(0);
+                }
+            }
+        }
+        self.yank_depth = 0;
+    }
+    /// The newest kill.
+    pub fn yank(&mut self) -> Option<&str> {
+        self.yank_depth = 0;
+        self.kills.last().map(String::as_str)
+    }
+    /// The kill before the one yanked last, going round to the newest after
+    /// the oldest.
+    #[allow(clippy::integer_arithmetic)]
+    pub fn yank_pop(&mut self) -> Option<&str> {
+        if self.kills.is_empty() {
+            return None;
+        }
+        self.yank_depth = self.yank_depth.saturating_add(1) % self.kills.len();
+        let index = self
+            .kills
+            .len()
+            .saturating_sub(1)
+            .saturating_sub(self.yank_depth);
+        self.kills.get(index).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_kill_and_yank() {
+        let mut ring = KillRing::default();
+        assert_eq!(ring.yank(), None);
+        ring.kill("one".to_string(), false);
+        ring.kill("two".to_string(), false);
+        ring.kill("\n".to_string(), true);
+        assert_eq!(ring.yank(), Some("two\n"));
+        assert_eq!(ring.yank_pop(), Some("one"));
+        assert_eq!(ring.yank_pop(), Some("two\n"));
+    }
+}
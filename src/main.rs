@@ -7,21 +7,100 @@
     clippy::wildcard_enum_match_arm,
     clippy::else_if_without_else
 )]
+mod args;
+mod backup;
+mod config;
 mod document;
 mod editor;
+mod encoding;
 mod filetype;
+mod fuzzy;
+#[cfg(test)]
+mod headless;
 mod highlighting;
+mod input;
+mod keymap;
+mod killring;
+mod pipe;
 mod row;
+mod screen;
+mod storage;
+mod swap;
+#[cfg(test)]
+mod tempdir;
 mod terminal;
+mod vim;
+mod watch;
+use args::Action;
+pub use args::Args;
+pub use backup::Backup;
+pub use config::Config;
 pub use document::Document;
+pub use document::LineEnding;
 use editor::Editor;
 pub use editor::Position;
 pub use editor::SearchDirection;
+pub use encoding::Encoding;
 pub use filetype::FileType;
 pub use filetype::HighlightingOptions;
+pub use filetype::IndentStyle;
+pub use filetype::Indentation;
+pub use input::Key;
 pub use row::Row;
+use std::env;
+use std::io::{self, Write};
+use std::process;
+pub use terminal::Backend;
+pub use terminal::Size;
 pub use terminal::Terminal;
 
 fn main() {
-    Editor::default().run();
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(Action::Edit(args)) => args,
+        Ok(Action::Help) => {
+            println!("{}", args::USAGE);
+            return;
+        }
+        Ok(Action::Version) => {
+            println!("hecto {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(error) => {
+            eprintln!("hecto: {}\nTry 'hecto --help' for more information.", error);
+            process::exit(2);
+        }
+    };
+    let stdout = if args.to_stdout || !termion::is_tty(&io::stdout()) {
+        match pipe::take_stdout() {
+            Ok(stdout) => Some(stdout),
+            Err(error) => {
+                eprintln!("hecto: cannot open the terminal: {}", error);
+                process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    let to_stdout = args.to_stdout;
+    let mut editor = match Editor::new(args) {
+        Ok(editor) => editor,
+        Err(error) => {
+            eprintln!("hecto: {}", error);
+            process::exit(1);
+        }
+    };
+    let result = editor.run();
+    let contents = editor.contents();
+    // Restore the terminal before anything else is printed.
+    drop(editor);
+    if let Err(error) = result {
+        eprintln!("hecto: {}", error);
+        process::exit(1);
+    }
+    if let (true, Some(mut stdout)) = (to_stdout, stdout) {
+        if let Err(error) = contents.and_then(|contents| stdout.write_all(&contents)) {
+            eprintln!("hecto: cannot write to stdout: {}", error);
+            process::exit(1);
+        }
+    }
 }
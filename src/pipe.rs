@@ -0,0 +1,38 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+/// Reads everything piped to hecto. Stdin is then pointed at the terminal,
+/// so keys can be read from it as usual.
+pub fn read_stdin() -> Result<Vec<u8>, io::Error> {
+    let mut bytes = Vec::new();
+    io::stdin().lock().read_to_end(&mut bytes)?;
+    if !termion::is_tty(&io::stdin()) {
+        attach_tty(libc::STDIN_FILENO)?;
+    }
+    Ok(bytes)
+}
+
+/// Sets the original stdout aside and points stdout at the terminal, so the
+/// editor can draw even when its output goes into a pipe.
+pub fn take_stdout() -> Result<File, io::Error> {
+    // SAFETY: dup takes no pointers; the new descriptor is owned by the
+    // `File` from here on.
+    let original = unsafe { libc::dup(libc::STDOUT_FILENO) };
+    if original < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `original` was just opened and nothing else owns it.
+    let original = unsafe { File::from_raw_fd(original) };
+    attach_tty(libc::STDOUT_FILENO)?;
+    Ok(original)
+}
+
+fn attach_tty(fd: RawFd) -> Result<(), io::Error> {
+    let tty = termion::get_tty()?;
+    // SAFETY: both descriptors are open; dup2 replaces `fd` atomically.
+    if unsafe { libc::dup2(tty.as_raw_fd(), fd) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -1,9 +1,13 @@
 use crate::highlighting;
 use crate::HighlightingOptions;
+use crate::LineEnding;
 use crate::SearchDirection;
 use std::cmp;
+use std::ops::Range;
 use termion::color;
+use termion::style;
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 #[derive(Default)]
 pub struct Row {
@@ -11,6 +15,8 @@ pub struct Row {
     highlighting: Vec<highlighting::Type>,
     pub is_highlighted: bool,
     len: usize,
+    /// How this row ended on disk, if that differs from the document.
+    line_ending: Option<LineEnding>,
 }
 
 impl From<&str> for Row {
@@ -20,24 +26,45 @@ impl From<&str> for Row {
             highlighting: Vec::new(),
             is_highlighted: false,
             len: slice.graphemes(true).count(),
+            line_ending: None,
         }
     }
 }
 
 impl Row {
-    pub fn render(&self, start: usize, end: usize) -> String {
-        let end = cmp::min(end, self.string.len());
-        let start = cmp::min(start, end);
+    /// Renders the columns `start..end` of this row, expanding tabs to the
+    /// next multiple of `tab_width`.
+    pub fn render(
+        &self,
+        start: usize,
+        end: usize,
+        selected: &Range<usize>,
+        tab_width: usize,
+    ) -> String {
         let mut result = String::new();
         let mut current_highlighting = &highlighting::Type::None;
-        #[allow(clippy::integer_arithmetic)]
-        for (index, grapheme) in self.string[..]
-            .graphemes(true)
-            .enumerate()
-            .skip(start)
-            .take(end - start)
-        {
+        let mut inverted = false;
+        let mut column = 0;
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            if column >= end {
+                break;
+            }
             if let Some(c) = grapheme.chars().next() {
+                let width = grapheme_width(grapheme, column, tab_width);
+                let visible = cmp::min(column.saturating_add(width), end)
+                    .saturating_sub(cmp::max(column, start));
+                column = column.saturating_add(width);
+                if visible == 0 {
+                    continue;
+                }
+                if selected.contains(&index) != inverted {
+                    inverted = !inverted;
+                    if inverted {
+                        result.push_str(&format!("{}", style::Invert));
+                    } else {
+                        result.push_str(&format!("{}", style::NoInvert));
+                    }
+                }
                 let highlighting_type = self
                     .highlighting
                     .get(index)
@@ -48,17 +75,44 @@ impl Row {
                         format!("{}", termion::color::Fg(highlighting_type.to_color()));
                     result.push_str(&start_highlight[..]);
                 }
-                if c == '\t' {
-                    result.push_str(" ");
+                if c == '\t' || visible < width {
+                    // Tabs, and wide characters cut off at the edge.
+                    result.push_str(&" ".repeat(visible));
+                } else if c.is_control() || grapheme.width() == 0 {
+                    result.push('?');
                 } else {
-                    result.push(c);
+                    result.push_str(grapheme);
                 }
             }
         }
+        if inverted {
+            result.push_str(&format!("{}", style::NoInvert));
+        }
         let end_highlight = format!("{}", termion::color::Fg(color::Reset));
         result.push_str(&end_highlight[..]);
         result
     }
+    /// The screen column at which grapheme `x` is drawn.
+    pub fn render_x(&self, x: usize, tab_width: usize) -> usize {
+        self.string[..]
+            .graphemes(true)
+            .take(x)
+            .fold(0, |column, grapheme| {
+                column.saturating_add(grapheme_width(grapheme, column, tab_width))
+            })
+    }
+    /// The grapheme drawn at screen column `column`, or the end of the row
+    /// for columns past it.
+    pub fn x_at_render_x(&self, column: usize, tab_width: usize) -> usize {
+        let mut render_x: usize = 0;
+        for (x, grapheme) in self.string[..].graphemes(true).enumerate() {
+            render_x = render_x.saturating_add(grapheme_width(grapheme, render_x, tab_width));
+            if render_x > column {
+                return x;
+            }
+        }
+        self.len
+    }
     pub fn len(&self) -> usize {
         self.len
     }
@@ -99,9 +153,11 @@ impl Row {
         self.len = length;
         self.string = result;
     }
+    /// Joins `new` onto the end of this row, which then ends as `new` did.
     pub fn append(&mut self, new: &Self) {
         self.string = format!("{}{}", self.string, new.string);
         self.len += new.len;
+        self.line_ending = new.line_ending;
     }
     pub fn split(&mut self, at: usize) -> Self {
         let mut row: String = String::new();
@@ -126,11 +182,100 @@ impl Row {
             len: splitted_length,
             is_highlighted: false,
             highlighting: Vec::new(),
+            line_ending: self.line_ending.take(),
+        }
+    }
+    pub fn insert_str(&mut self, at: usize, string: &str) {
+        let mut result: String = self.string[..].graphemes(true).take(at).collect();
+        result.push_str(string);
+        result.extend(self.string[..].graphemes(true).skip(at));
+        self.len = result[..].graphemes(true).count();
+        self.string = result;
+    }
+    /// The grapheme ranges of the words and punctuation runs in this row.
+    /// Word boundaries follow Unicode word segmentation, additionally split
+    /// wherever identifier characters meet other characters.
+    #[allow(clippy::integer_arithmetic)]
+    pub fn words(&self) -> Vec<Range<usize>> {
+        let mut words: Vec<(Range<usize>, CharClass)> = Vec::new();
+        let mut index = 0;
+        for segment in self.string.split_word_bounds() {
+            let mut segment_start = true;
+            for grapheme in segment.graphemes(true) {
+                let class = CharClass::of(grapheme);
+                match words.last_mut() {
+                    Some((word, last_class))
+                        if word.end == index
+                            && *last_class == class
+                            && (!segment_start || class == CharClass::Punctuation) =>
+                    {
+                        word.end += 1;
+                    }
+                    _ if class == CharClass::Whitespace => (),
+                    _ => words.push((index..index + 1, class)),
+                }
+                segment_start = false;
+                index += 1;
+            }
         }
+        words.into_iter().map(|(word, _)| word).collect()
+    }
+    /// The end of the word at or after `at`.
+    pub fn next_word_end(&self, at: usize) -> usize {
+        self.words()
+            .into_iter()
+            .find(|word| word.end > at)
+            .map_or(self.len, |word| word.end)
+    }
+    /// The start of the word before `at`.
+    pub fn previous_word_start(&self, at: usize) -> usize {
+        self.words()
+            .into_iter()
+            .rev()
+            .find(|word| word.start < at)
+            .map_or(0, |word| word.start)
+    }
+    /// The word or punctuation run at `at`, or an empty range between
+    /// words.
+    pub fn word_at(&self, at: usize) -> Range<usize> {
+        self.words()
+            .into_iter()
+            .find(|word| word.contains(&at))
+            .unwrap_or(at..at)
+    }
+    pub fn char_at(&self, at: usize) -> Option<char> {
+        self.string[..].graphemes(true).nth(at)?.chars().next()
+    }
+    pub fn line_ending(&self) -> Option<LineEnding> {
+        self.line_ending
+    }
+    pub fn set_line_ending(&mut self, line_ending: Option<LineEnding>) {
+        self.line_ending = line_ending;
     }
     pub fn as_bytes(&self) -> &[u8] {
         self.string.as_bytes()
     }
+    pub fn as_str(&self) -> &str {
+        &self.string
+    }
+    /// Removes the spaces and tabs at the start of the row.
+    pub fn trim_start(&mut self) {
+        self.string = self.string.trim_start_matches([' ', '\t']).to_string();
+        self.len = self.string[..].graphemes(true).count();
+        self.is_highlighted = false;
+    }
+    /// Removes the spaces and tabs at the end of the row.
+    pub fn trim_end(&mut self) {
+        let length = self.string.trim_end_matches([' ', '\t']).len();
+        self.string.truncate(length);
+        self.len = self.string[..].graphemes(true).count();
+        self.is_highlighted = false;
+    }
+    pub fn leading_whitespace(&self) -> &str {
+        let trimmed = self.string.trim_start_matches([' ', '\t']);
+        #[allow(clippy::indexing_slicing, clippy::integer_arithmetic)]
+        &self.string[..self.string.len() - trimmed.len()]
+    }
     pub fn find(&self, query: &str, at: usize, direction: SearchDirection) -> Option<usize> {
         if at > self.len || query.is_empty() {
             return None;
@@ -463,6 +608,40 @@ impl Row {
     }
 }
 
+#[derive(PartialEq, Clone, Copy)]
+enum CharClass {
+    Identifier,
+    Whitespace,
+    Punctuation,
+}
+
+impl CharClass {
+    fn of(grapheme: &str) -> Self {
+        match grapheme.chars().next() {
+            Some(c) if c.is_whitespace() => Self::Whitespace,
+            Some(c) if c.is_alphanumeric() || c == '_' => Self::Identifier,
+            _ => Self::Punctuation,
+        }
+    }
+}
+
+/// The number of columns a tab at `column` takes up.
+#[allow(clippy::integer_arithmetic)]
+fn tab_stop(column: usize, tab_width: usize) -> usize {
+    let tab_width = cmp::max(tab_width, 1);
+    tab_width - column % tab_width
+}
+
+/// The number of columns `grapheme` takes up when drawn at `column`. Wide
+/// characters take two, and characters drawn as `?` one.
+fn grapheme_width(grapheme: &str, column: usize, tab_width: usize) -> usize {
+    if grapheme == "\t" {
+        tab_stop(column, tab_width)
+    } else {
+        grapheme.width().max(1)
+    }
+}
+
 fn is_separator(c: char) -> bool {
     c.is_ascii_punctuation() || c.is_ascii_whitespace()
 }
@@ -502,6 +681,38 @@ mod test_super {
         )
     }
 
+    #[test]
+    fn test_word_motion() {
+        let row = Row::from("    let foo_bar = self.baz();");
+        assert_eq!(row.next_word_end(0), 7);
+        assert_eq!(row.next_word_end(7), 15);
+        assert_eq!(row.next_word_end(15), 17);
+        assert_eq!(row.next_word_end(26), 29);
+        assert_eq!(row.previous_word_start(15), 8);
+        assert_eq!(row.previous_word_start(8), 4);
+        assert_eq!(row.previous_word_start(4), 0);
+        assert_eq!(row.word_at(10), 8..15);
+        assert_eq!(row.word_at(3), 3..3);
+    }
+
+    #[test]
+    fn test_x_at_render_x() {
+        let row = Row::from("\tab");
+        assert_eq!(row.x_at_render_x(3, 4), 0);
+        assert_eq!(row.x_at_render_x(4, 4), 1);
+        assert_eq!(row.x_at_render_x(9, 4), 3);
+        assert_eq!(row.render_x(row.x_at_render_x(5, 4), 4), 5);
+    }
+
+    #[test]
+    fn test_wide_characters() {
+        let row = Row::from("日本x");
+        assert_eq!(row.render_x(2, 4), 4);
+        assert_eq!(row.x_at_render_x(3, 4), 1);
+        assert_eq!(row.x_at_render_x(4, 4), 2);
+        assert!(row.render(1, 5, &(0..0), 4).starts_with(" 本"));
+    }
+
     #[test]
     fn test_find() {
         let row = Row::from("1testtest");
@@ -0,0 +1,284 @@
+use crate::Position;
+use std::fmt::Write;
+use termion::color;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Default, Clone, Copy, PartialEq)]
+struct Style {
+    fg: Option<color::Rgb>,
+    bg: Option<color::Rgb>,
+    invert: bool,
+}
+
+impl Style {
+    /// Applies the SGR escape sequence with the given parameters, as written
+    /// by termion's color and style types.
+    fn apply(&mut self, parameters: &str) {
+        let numbers: Vec<u8> = parameters
+            .split(';')
+            .map(|number| number.parse().unwrap_or(0))
+            .collect();
+        match numbers.as_slice() {
+            [0] => *self = Self::default(),
+            [7] => self.invert = true,
+            [27] => self.invert = false,
+            [39] => self.fg = None,
+            [49] => self.bg = None,
+            [38, 2, r, g, b] => self.fg = Some(color::Rgb(*r, *g, *b)),
+            [48, 2, r, g, b] => self.bg = Some(color::Rgb(*r, *g, *b)),
+            _ => (),
+        }
+    }
+    /// The escape sequences that change the terminal from `self` to `to`.
+    fn transition(self, to: Self, output: &mut String) {
+        if self.fg != to.fg {
+            match to.fg {
+                Some(rgb) => write!(output, "{}", color::Fg(rgb)),
+                None => write!(output, "{}", color::Fg(color::Reset)),
+            }
+            .ok();
+        }
+        if self.bg != to.bg {
+            match to.bg {
+                Some(rgb) => write!(output, "{}", color::Bg(rgb)),
+                None => write!(output, "{}", color::Bg(color::Reset)),
+            }
+            .ok();
+        }
+        if self.invert != to.invert {
+            if to.invert {
+                write!(output, "{}", termion::style::Invert).ok();
+            } else {
+                write!(output, "{}", termion::style::NoInvert).ok();
+            }
+        }
+    }
+}
+
+/// One terminal column. A wide grapheme fills its cell and the one after
+/// it, which is left with an empty grapheme.
+#[derive(Clone, PartialEq)]
+struct Cell {
+    grapheme: String,
+    style: Style,
+}
+
+impl Cell {
+    fn width(&self) -> usize {
+        self.grapheme.width()
+    }
+    fn is_continuation(&self) -> bool {
+        self.grapheme.is_empty()
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            grapheme: " ".to_string(),
+            style: Style::default(),
+        }
+    }
+}
+
+/// An off-screen grid of styled cells that a frame is drawn into, so only
+/// what changed since the previous frame has to be sent to the terminal.
+#[derive(Clone)]
+pub struct Screen {
+    cells: Vec<Vec<Cell>>,
+    cursor: Position,
+    style: Style,
+}
+
+impl Screen {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            cells: vec![vec![Cell::default(); width as usize]; height as usize],
+            cursor: Position::default(),
+            style: Style::default(),
+        }
+    }
+    pub fn cursor(&self) -> &Position {
+        &self.cursor
+    }
+    pub fn move_to(&mut self, position: &Position) {
+        self.cursor = position.clone();
+    }
+    pub fn clear_line(&mut self) {
+        if let Some(line) = self.cells.get_mut(self.cursor.y) {
+            line.iter_mut().for_each(|cell| *cell = Cell::default());
+        }
+    }
+    pub fn set_fg(&mut self, fg: Option<color::Rgb>) {
+        self.style.fg = fg;
+    }
+    pub fn set_bg(&mut self, bg: Option<color::Rgb>) {
+        self.style.bg = bg;
+    }
+    /// Writes `text` at the cursor, one grapheme per cell or two for wide
+    /// ones. SGR escape sequences in it change the style of the cells that
+    /// follow; `\r` and `\n` move the cursor as on a terminal in raw mode.
+    pub fn print(&mut self, text: &str) {
+        let mut rest = text;
+        while !rest.is_empty() {
+            if let Some(sequence) = rest.strip_prefix("\x1b[") {
+                // A control sequence runs up to its final byte.
+                let end = sequence
+                    .find(|c| ('@'..='~').contains(&c))
+                    .unwrap_or_else(|| sequence.len());
+                let (parameters, tail) = sequence.split_at(end);
+                if tail.starts_with('m') {
+                    self.style.apply(parameters);
+                }
+                rest = tail.get(1..).unwrap_or_default();
+                continue;
+            }
+            let grapheme = rest.graphemes(true).next().unwrap_or_default();
+            rest = rest.get(grapheme.len()..).unwrap_or_default();
+            match grapheme {
+                "\r" => self.cursor.x = 0,
+                "\n" => self.cursor.y = self.cursor.y.saturating_add(1),
+                "\r\n" => {
+                    self.cursor.x = 0;
+                    self.cursor.y = self.cursor.y.saturating_add(1);
+                }
+                _ => self.put(grapheme),
+            }
+        }
+    }
+    /// Writes `grapheme` at the cursor and moves past it. Whatever is left of
+    /// a wide grapheme it partly covers is blanked, and a wide grapheme that
+    /// does not fit on the line is drawn as a blank.
+    fn put(&mut self, grapheme: &str) {
+        let Position { x, y } = self.cursor.clone();
+        let style = self.style;
+        let line = if let Some(line) = self.cells.get_mut(y) {
+            line
+        } else {
+            return;
+        };
+        let fits = x.saturating_add(grapheme.width()) <= line.len();
+        let (grapheme, width) = if fits {
+            (grapheme, grapheme.width().max(1))
+        } else {
+            (" ", 1)
+        };
+        let end = x.saturating_add(width);
+        if line.get(x).map_or(false, Cell::is_continuation) {
+            if let Some(before) = x.checked_sub(1).and_then(|before| line.get_mut(before)) {
+                *before = Cell::default();
+            }
+        }
+        if line.get(end).map_or(false, Cell::is_continuation) {
+            if let Some(after) = line.get_mut(end) {
+                *after = Cell::default();
+            }
+        }
+        for (index, cell) in line.iter_mut().enumerate().skip(x).take(width) {
+            *cell = Cell {
+                grapheme: if index == x {
+                    grapheme.to_string()
+                } else {
+                    String::new()
+                },
+                style,
+            };
+        }
+        self.cursor.x = end;
+    }
+    /// The text of line `y`, without styles and trailing blanks.
+    #[cfg(test)]
+    pub fn line(&self, y: usize) -> String {
+        self.cells.get(y).map_or_else(String::new, |line| {
+            let text: String = line.iter().map(|cell| cell.grapheme.as_str()).collect();
+            text.trim_end().to_string()
+        })
+    }
+    #[cfg(test)]
+    pub fn height(&self) -> usize {
+        self.cells.len()
+    }
+    /// The output that turns a terminal showing `shown`, or anything at all
+    /// if there is no `shown`, into this screen: only the changed cells are
+    /// written, moving the cursor only where they are not adjacent and
+    /// changing colors only where they differ. Empty if nothing changed.
+    pub fn diff(&self, shown: Option<&Self>) -> String {
+        let mut output = String::new();
+        let mut style = Style::default();
+        let mut at: Option<Position> = None;
+        let blank = Cell::default();
+        if shown.is_none() {
+            write!(output, "{}{}", termion::style::Reset, termion::clear::All).ok();
+        }
+        for (y, line) in self.cells.iter().enumerate() {
+            for (x, cell) in line.iter().enumerate() {
+                // A cleared terminal shows blanks.
+                let old = shown.map_or(Some(&blank), |shown| shown.cells.get(y)?.get(x));
+                // The second half of a wide grapheme is drawn along with it.
+                if old == Some(cell) || cell.is_continuation() {
+                    continue;
+                }
+                if at != Some(Position { x, y }) {
+                    write!(output, "{}", goto(&Position { x, y })).ok();
+                }
+                style.transition(cell.style, &mut output);
+                style = cell.style;
+                output.push_str(&cell.grapheme);
+                at = Some(Position {
+                    x: x.saturating_add(cell.width().max(1)),
+                    y,
+                });
+            }
+        }
+        style.transition(Style::default(), &mut output);
+        output
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+pub fn goto(position: &Position) -> termion::cursor::Goto {
+    termion::cursor::Goto(
+        position.x.saturating_add(1) as u16,
+        position.y.saturating_add(1) as u16,
+    )
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_diff_writes_only_changes() {
+        let mut shown = Screen::new(10, 2);
+        shown.print("hello\r\nworld");
+        let mut next = shown.clone();
+        next.move_to(&Position { x: 1, y: 1 });
+        next.print("\x1b[38;2;1;2;3mO\x1b[39mR");
+        assert_eq!(next.line(1), "wORld");
+        assert_eq!(
+            next.diff(Some(&shown)),
+            "\x1b[2;2H\x1b[38;2;1;2;3mO\x1b[39mR"
+        );
+        assert_eq!(next.diff(Some(&next)), "");
+    }
+
+    #[test]
+    fn test_wide_characters() {
+        let mut shown = Screen::new(6, 1);
+        shown.print("日ab");
+        assert_eq!(shown.line(0), "日ab");
+        let mut next = shown.clone();
+        next.move_to(&Position { x: 3, y: 0 });
+        next.print("B");
+        // The terminal column after a wide character is one further on.
+        assert_eq!(next.diff(Some(&shown)), "\x1b[1;4HB");
+
+        next.move_to(&Position { x: 1, y: 0 });
+        next.print("x");
+        next.move_to(&Position { x: 5, y: 0 });
+        next.print("本");
+        assert_eq!(next.line(0), " xaB");
+        assert_eq!(next.diff(Some(&shown)), "\x1b[1;1H x\x1b[1;4HB");
+    }
+}
@@ -0,0 +1,154 @@
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::fs::{chown, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Replaces the contents of `path` with `contents` so that a crash or a full
+/// disk leaves either the old or the new file behind, never a truncated one.
+///
+/// The data goes to a temporary file in the same directory, which gets the
+/// original's permissions and ownership, is synced to disk and then renamed
+/// over the original. If that is not possible, because the directory is not
+/// writable or the owner cannot be kept, the file is overwritten in place.
+pub fn write_file(path: &Path, contents: &[u8]) -> Result<(), io::Error> {
+    write_file_with_mode(path, contents, 0o666)
+}
+
+/// Like `write_file`, but a file that does not exist yet is created with
+/// `mode` rather than readable by everyone the umask allows.
+pub fn write_file_with_mode(path: &Path, contents: &[u8], mode: u32) -> Result<(), io::Error> {
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let metadata = match fs::metadata(&target) {
+        Ok(metadata) => Some(metadata),
+        Err(error) if error.kind() == ErrorKind::NotFound => None,
+        Err(error) => return Err(error),
+    };
+    let temp_path = temp_path(&target);
+    // Start out no more permissive than the original, so its contents are
+    // never readable by anyone who could not read the original.
+    let mode = metadata
+        .as_ref()
+        .map_or(mode, |metadata| metadata.permissions().mode() & 0o777);
+    let mut temp = match create_temp(&temp_path, mode) {
+        Ok(file) => file,
+        Err(error) if error.kind() == ErrorKind::PermissionDenied => {
+            return write_in_place(&target, contents);
+        }
+        Err(error) => return Err(error),
+    };
+    let result = fill_temp_file(&mut temp, &temp_path, contents, metadata.as_ref())
+        .and_then(|_| fs::rename(&temp_path, &target));
+    if let Err(error) = result {
+        // Nothing has touched the original yet, so clean up and report.
+        fs::remove_file(&temp_path).ok();
+        if error.kind() == ErrorKind::PermissionDenied && metadata.is_some() {
+            return write_in_place(&target, contents);
+        }
+        return Err(error);
+    }
+    // Make the rename itself durable. Not every file system supports
+    // syncing a directory, and the data is already safe, so this is best
+    // effort.
+    if let Some(dir) = target.parent() {
+        if let Ok(dir) = File::open(dir_or_current(dir)) {
+            dir.sync_all().ok();
+        }
+    }
+    Ok(())
+}
+
+/// Whether the existing file at `path` can be opened for writing.
+pub fn is_writable(path: &Path) -> bool {
+    OpenOptions::new().append(true).open(path).is_ok()
+}
+
+fn fill_temp_file(
+    temp: &mut File,
+    temp_path: &Path,
+    contents: &[u8],
+    original: Option<&Metadata>,
+) -> Result<(), io::Error> {
+    if let Some(original) = original {
+        fs::set_permissions(temp_path, original.permissions())?;
+        let metadata = temp.metadata()?;
+        if metadata.uid() != original.uid() || metadata.gid() != original.gid() {
+            chown(temp_path, Some(original.uid()), Some(original.gid()))?;
+        }
+    }
+    temp.write_all(contents)?;
+    temp.sync_all()
+}
+
+/// Creates the temporary file at `path` with `mode`, replacing one left
+/// behind by a crashed save of an earlier process that had the same pid.
+fn create_temp(path: &Path, mode: u32) -> Result<File, io::Error> {
+    let create = || {
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode)
+            .open(path)
+    };
+    match create() {
+        Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+            fs::remove_file(path)?;
+            create()
+        }
+        result => result,
+    }
+}
+
+fn write_in_place(path: &Path, contents: &[u8]) -> Result<(), io::Error> {
+    let mut file = File::create(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+fn temp_path(target: &Path) -> PathBuf {
+    let name = target
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().to_string());
+    let temp_name = format!(".{}.{}.tmp", name, process::id());
+    target.with_file_name(temp_name)
+}
+
+fn dir_or_current(dir: &Path) -> &Path {
+    if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    }
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+    use crate::tempdir::TempDir;
+
+    #[test]
+    fn test_write_file_keeps_permissions() {
+        let dir = TempDir::new("storage");
+        let path = dir.join("notes.txt");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        write_file(&path, b"new").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[test]
+    fn test_write_file_replaces_stale_temp_file() {
+        let dir = TempDir::new("stale");
+        let path = dir.join("notes.txt");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        fs::write(temp_path(&path), "left over").unwrap();
+        write_file(&path, b"new").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(!temp_path(&path).exists());
+    }
+}
@@ -0,0 +1,137 @@
+use crate::storage;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process;
+
+const MAGIC: &str = "hecto swap";
+/// Above this many cells the diff gives up on finding common lines.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// A swap file left behind for a document.
+pub struct Swap {
+    pub text: String,
+    /// The process that wrote the swap file, if it is still running.
+    pub running_pid: Option<u32>,
+}
+
+/// The swap file for `file_name`: `.name.hswp` next to it.
+pub fn path(file_name: &str) -> PathBuf {
+    let path = Path::new(file_name);
+    let name = path
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().to_string());
+    path.with_file_name(format!(".{}.hswp", name))
+}
+
+/// Writes the swap file like a save, so a crash midway leaves the previous
+/// swap file rather than a truncated one to recover from.
+pub fn write(path: &Path, text: &str) -> Result<(), io::Error> {
+    let contents = format!("{} {}\n{}", MAGIC, process::id(), text);
+    storage::write_file_with_mode(path, contents.as_bytes(), 0o600)
+}
+
+/// Reads the swap file at `path`, ignoring files that were not written by
+/// hecto.
+pub fn read(path: &Path) -> Result<Option<Swap>, io::Error> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(error) if error.kind() == ErrorKind::InvalidData => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    let mut parts = contents.splitn(2, '\n');
+    let header = parts.next().unwrap_or_default();
+    let pid = if let Some(pid) = header.strip_prefix(MAGIC) {
+        pid.trim().parse::<u32>().ok()
+    } else {
+        return Ok(None);
+    };
+    let running_pid =
+        pid.filter(|pid| *pid != process::id() && Path::new(&format!("/proc/{}", pid)).exists());
+    Ok(Some(Swap {
+        text: parts.next().unwrap_or_default().to_string(),
+        running_pid,
+    }))
+}
+
+pub fn remove(path: &Path) {
+    // A missing swap file is what we want anyway.
+    fs::remove_file(path).ok();
+}
+
+/// A line diff from `old` to `new`: unchanged lines are left out, removed
+/// lines start with `-`, added lines with `+`, and every hunk is headed by
+/// the line number it starts at.
+#[allow(clippy::indexing_slicing, clippy::integer_arithmetic)]
+pub fn diff(old: &[&str], new: &[&str]) -> Vec<String> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+    let mut lines = Vec::new();
+    if old.is_empty() && new.is_empty() {
+        return lines;
+    }
+    // Longest common subsequence lengths of every pair of suffixes.
+    let cells = (old.len() + 1).saturating_mul(new.len() + 1);
+    let mut common = vec![0_usize; if cells <= MAX_DIFF_CELLS { cells } else { 0 }];
+    let width = new.len() + 1;
+    if !common.is_empty() {
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                common[i * width + j] = if old[i] == new[j] {
+                    common[(i + 1) * width + j + 1] + 1
+                } else {
+                    common[(i + 1) * width + j].max(common[i * width + j + 1])
+                };
+            }
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut in_hunk = false;
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] && !common.is_empty() {
+            i += 1;
+            j += 1;
+            in_hunk = false;
+            continue;
+        }
+        if !in_hunk {
+            lines.push(format!("@@ line {} @@", prefix + i + 1));
+            in_hunk = true;
+        }
+        let remove = j >= new.len()
+            || (i < old.len()
+                && (common.is_empty() || common[(i + 1) * width + j] >= common[i * width + j + 1]));
+        if remove {
+            lines.push(format!("-{}", old[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+{}", new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let old = ["a", "b", "c", "d"];
+        let new = ["a", "x", "c", "d", "e"];
+        assert_eq!(
+            diff(&old, &new),
+            vec!["@@ line 2 @@", "-b", "+x", "@@ line 5 @@", "+e"]
+        );
+        assert!(diff(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,40 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// A directory of its own for a test's files, which is removed when it is
+/// dropped, so also when the test fails halfway.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates `hecto-<name>-<pid>` in the system's temporary directory,
+    /// emptying one that an earlier run left behind.
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("hecto-{}-{}", name, process::id()));
+        fs::remove_dir_all(&path).ok();
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+    /// Writes `contents` to the file `name` in the directory and returns
+    /// its path as documents take it.
+    pub fn write(&self, name: &str, contents: &str) -> String {
+        let path = self.join(name);
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.path).ok();
+    }
+}
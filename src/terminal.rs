@@ -1,75 +1,238 @@
+use crate::input::{self, PASTE_START};
+use crate::screen::{self, Screen};
+use crate::Key;
 use crate::Position;
-use std::io::{self, stdout, Write};
+use std::io::{self, stdout, ErrorKind, Stdout, Write};
+use std::mem;
+use std::panic;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
 use termion::color;
-use termion::event::Key;
-use termion::input::TermRead;
+use termion::event::Event;
+use termion::input::{MouseTerminal, TermRead};
 use termion::raw::{IntoRawMode, RawTerminal};
+use termion::screen::{AlternateScreen, ToMainScreen};
 
+/// Turns off the mouse reporting `MouseTerminal` turns on.
+const EXIT_MOUSE_SEQUENCE: &str = "\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l";
+const ENTER_PASTE_SEQUENCE: &str = "\x1b[?2004h";
+const EXIT_PASTE_SEQUENCE: &str = "\x1b[?2004l";
+
+#[derive(Clone, Copy)]
 pub struct Size {
     pub width: u16,
     pub height: u16,
 }
+
+/// What the editor needs from a terminal: its size, a way to draw on it and
+/// the keys typed. `height` leaves out the two lines of the status and
+/// message bars.
+pub trait Backend {
+    fn size(&self) -> &Size;
+    fn clear_current_line(&mut self);
+    fn cursor_position(&mut self, position: &Position);
+    fn cursor_hide(&mut self);
+    fn cursor_show(&mut self);
+    fn set_bg_color(&mut self, color: color::Rgb);
+    fn reset_bg_color(&mut self);
+    fn set_fg_color(&mut self, color: color::Rgb);
+    fn reset_fg_color(&mut self);
+    /// Writes `text` at the cursor. It may contain color escape sequences,
+    /// `\r` and `\n`.
+    fn print(&mut self, text: &str);
+    /// Shows what was drawn since the last flush.
+    fn flush(&mut self) -> Result<(), io::Error>;
+    /// Waits for the next key.
+    fn read_key(&mut self) -> Result<Key, io::Error>;
+    /// Waits at most `timeout` for the next key, or returns `None`.
+    fn poll_key(&mut self, timeout: Duration) -> Result<Option<Key>, io::Error>;
+}
+
+/// The terminal hecto runs in, driven through termion. Frames are drawn
+/// into an off-screen `Screen` and `flush` sends only the difference to the
+/// previous frame, in a single write.
+///
+/// hecto draws on the alternate screen, leaving the shell's scrollback
+/// alone, with mouse reporting and bracketed paste on. Dropping the
+/// terminal switches back to the main screen and restores the cursor, the
+/// mouse, pasting and cooked mode.
+///
+/// Keys are read on a thread of their own, so the editor can wait for them
+/// with a timeout.
 pub struct Terminal {
     size: Size,
-    _stdout: RawTerminal<std::io::Stdout>,
+    frame: Screen,
+    /// What the terminal shows, unless it is in an unknown state.
+    shown: Option<Screen>,
+    cursor_visible: bool,
+    /// Where the terminal shows the cursor and whether it is visible.
+    shown_cursor: Option<(Position, bool)>,
+    stdout: AlternateScreen<MouseTerminal<RawTerminal<Stdout>>>,
+    /// Started by the first read, after anything piped to stdin is read.
+    keys: Option<Receiver<Result<Key, io::Error>>>,
 }
 
 impl Terminal {
     pub fn default() -> Result<Self, std::io::Error> {
         let size = termion::terminal_size()?;
-        Ok(Self {
+        restore_on_panic();
+        let mut terminal = Self {
             size: Size {
                 width: size.0,
                 height: size.1.saturating_sub(2),
             },
-            _stdout: stdout().into_raw_mode()?,
-        })
+            frame: Screen::new(size.0, size.1),
+            shown: None,
+            cursor_visible: true,
+            shown_cursor: None,
+            stdout: AlternateScreen::from(MouseTerminal::from(stdout().into_raw_mode()?)),
+            keys: None,
+        };
+        write!(terminal.stdout, "{}", ENTER_PASTE_SEQUENCE)?;
+        Ok(terminal)
     }
-    pub fn size(&self) -> &Size {
-        &self.size
-    }
-    pub fn clear_screen() {
-        print!("{}", termion::clear::All);
+    fn keys(&mut self) -> &Receiver<Result<Key, io::Error>> {
+        self.keys.get_or_insert_with(spawn_key_reader)
     }
+}
 
-    #[allow(clippy::cast_possible_truncation)]
-    pub fn cursor_position(position: &Position) {
-        let Position { mut x, mut y } = position;
-        x = x.saturating_add(1);
-        y = y.saturating_add(1);
-        let x = x as u16;
-        let y = y as u16;
-        print!("{}", termion::cursor::Goto(x, y));
-    }
-    pub fn flush() -> Result<(), std::io::Error> {
-        io::stdout().flush()
-    }
-    pub fn read_key() -> Result<Key, std::io::Error> {
+/// Reads keys from stdin until it fails or the receiver goes away.
+fn spawn_key_reader() -> Receiver<Result<Key, io::Error>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        // The iterator holds back a byte when two keys arrive together, so
+        // it has to last. Pastes are read from stdin past it, which is safe
+        // as nothing is held back after an escape sequence.
+        let mut events = io::stdin().events();
         loop {
-            if let Some(key) = io::stdin().lock().keys().next() {
-                return key;
+            let key = match events.next() {
+                Some(Ok(event)) if event == Event::Unsupported(PASTE_START.to_vec()) => {
+                    input::read_paste(&mut io::stdin()).map(Key::Paste)
+                }
+                Some(Ok(event)) => match Key::from_event(event) {
+                    Some(key) => Ok(key),
+                    None => continue,
+                },
+                Some(Err(error)) if error.kind() == ErrorKind::Interrupted => continue,
+                Some(Err(error)) => Err(error),
+                None => Err(input_closed()),
+            };
+            let failed = key.is_err();
+            if sender.send(key).is_err() || failed {
+                return;
             }
         }
+    });
+    receiver
+}
+
+fn input_closed() -> io::Error {
+    io::Error::new(ErrorKind::UnexpectedEof, "the terminal closed")
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // The fields undo the alternate screen, mouse reporting and raw mode
+        // as they drop.
+        write!(
+            self.stdout,
+            "{}{}{}",
+            termion::style::Reset,
+            termion::cursor::Show,
+            EXIT_PASTE_SEQUENCE
+        )
+        .ok();
+        self.stdout.flush().ok();
+    }
+}
+
+/// Makes a panic put the terminal back in cooked mode on the main screen
+/// before the panic message is printed, which would otherwise end up
+/// garbled and then vanish with the alternate screen.
+fn restore_on_panic() {
+    // SAFETY: termios is plain data, and tcgetattr fills it in.
+    let mut original: libc::termios = unsafe { mem::zeroed() };
+    // SAFETY: `original` is a valid termios to write to.
+    if unsafe { libc::tcgetattr(libc::STDOUT_FILENO, &mut original) } != 0 {
+        return;
+    }
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // SAFETY: `original` holds the settings read above.
+        unsafe {
+            libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, &original);
+        }
+        print!(
+            "{}{}{}{}{}",
+            termion::style::Reset,
+            termion::cursor::Show,
+            ToMainScreen,
+            EXIT_MOUSE_SEQUENCE,
+            EXIT_PASTE_SEQUENCE
+        );
+        io::stdout().flush().ok();
+        default_hook(info);
+    }));
+}
+
+impl Backend for Terminal {
+    fn size(&self) -> &Size {
+        &self.size
+    }
+    fn cursor_position(&mut self, position: &Position) {
+        self.frame.move_to(position);
+    }
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        let changes = self.frame.diff(self.shown.as_ref());
+        let cursor = (self.frame.cursor().clone(), self.cursor_visible);
+        // Idle redraws mostly change nothing.
+        if changes.is_empty() && self.shown_cursor.as_ref() == Some(&cursor) {
+            return Ok(());
+        }
+        let mut output = format!("{}{}", termion::cursor::Hide, changes);
+        output.push_str(&screen::goto(self.frame.cursor()).to_string());
+        if self.cursor_visible {
+            output.push_str(&termion::cursor::Show.to_string());
+        }
+        self.stdout.write_all(output.as_bytes())?;
+        self.stdout.flush()?;
+        self.shown = Some(self.frame.clone());
+        self.shown_cursor = Some(cursor);
+        Ok(())
+    }
+    fn read_key(&mut self) -> Result<Key, std::io::Error> {
+        self.keys().recv().map_err(|_| input_closed())?
+    }
+    fn poll_key(&mut self, timeout: Duration) -> Result<Option<Key>, std::io::Error> {
+        match self.keys().recv_timeout(timeout) {
+            Ok(key) => key.map(Some),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(input_closed()),
+        }
+    }
+    fn cursor_hide(&mut self) {
+        self.cursor_visible = false;
     }
-    pub fn cursor_hide() {
-        print!("{}", termion::cursor::Hide);
+    fn cursor_show(&mut self) {
+        self.cursor_visible = true;
     }
-    pub fn cursor_show() {
-        print!("{}", termion::cursor::Show);
+    fn clear_current_line(&mut self) {
+        self.frame.clear_line();
     }
-    pub fn clear_current_line() {
-        print!("{}", termion::clear::CurrentLine);
+    fn set_bg_color(&mut self, color: color::Rgb) {
+        self.frame.set_bg(Some(color));
     }
-    pub fn set_bg_color(color: color::Rgb) {
-        print!("{}", color::Bg(color));
+    fn reset_bg_color(&mut self) {
+        self.frame.set_bg(None);
     }
-    pub fn reset_bg_color() {
-        print!("{}", color::Bg(color::Reset));
+    fn set_fg_color(&mut self, color: color::Rgb) {
+        self.frame.set_fg(Some(color));
     }
-    pub fn set_fg_color(color: color::Rgb) {
-        print!("{}", color::Fg(color));
+    fn reset_fg_color(&mut self) {
+        self.frame.set_fg(None);
     }
-    pub fn reset_fg_color() {
-        print!("{}", color::Fg(color::Reset));
+    fn print(&mut self, text: &str) {
+        self.frame.print(text);
     }
 }
@@ -0,0 +1,661 @@
+use crate::Document;
+use crate::Position;
+use crate::Row;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Counts above this are cut down to it: nobody means more, and a put or a
+/// motion repeated that often would run out of memory or time.
+pub const MAX_COUNT: usize = 10_000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Normal => "NORMAL",
+            Self::Insert => "INSERT",
+            Self::Visual => "VISUAL",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// `w`
+    WordStart,
+    /// `b`
+    WordBackward,
+    /// `e`
+    WordEnd,
+    /// `0`
+    LineStart,
+    /// `^`
+    FirstNonBlank,
+    /// `$`
+    LineEnd,
+    /// `gg`, or the line given by the count.
+    FirstLine,
+    /// `G`, or the line given by the count.
+    LastLine,
+}
+
+impl Motion {
+    /// Whether an operator on the motion works on whole lines.
+    pub fn is_linewise(self) -> bool {
+        matches!(
+            self,
+            Self::Up | Self::Down | Self::FirstLine | Self::LastLine
+        )
+    }
+    /// Whether an operator takes in the character the motion ends on.
+    pub fn is_inclusive(self) -> bool {
+        matches!(self, Self::WordEnd | Self::LineEnd)
+    }
+}
+
+/// A text object, such as the `w` in `diw` or the `(` in `ca(`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Object {
+    Word,
+    Pair(char, char),
+    Quote(char),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+/// What an operator works on.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Target {
+    Motion(Motion),
+    /// `iw` with `inner`, `aw` without.
+    Object {
+        object: Object,
+        inner: bool,
+    },
+    /// The operator typed twice, as in `dd`: the line and `count - 1` more.
+    Lines,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    Move(Motion),
+    Operate(Operator, Target),
+    /// `i`
+    Insert,
+    /// `a`
+    Append,
+    /// `I`
+    InsertAtLineStart,
+    /// `A`
+    AppendAtLineEnd,
+    /// `o`
+    OpenBelow,
+    /// `O`
+    OpenAbove,
+    /// `x`
+    DeleteChar,
+    /// `p`, or `P` with `before`.
+    Put {
+        before: bool,
+    },
+    /// `v`
+    Visual,
+    /// `:`
+    CommandLine,
+}
+
+/// How far the keys typed in normal mode got.
+#[derive(PartialEq, Debug)]
+pub enum Parse {
+    /// More keys are needed.
+    Pending,
+    Invalid,
+    /// An action, with the count typed for it if there was one.
+    Done(Option<usize>, Action),
+}
+
+/// Text that was yanked or deleted, for putting back.
+#[derive(Default)]
+pub struct Register {
+    pub text: String,
+    /// Whether the text is whole lines, which are put on lines of their own.
+    pub linewise: bool,
+}
+
+/// The state of modal editing.
+pub struct Vim {
+    pub mode: Mode,
+    /// The keys typed so far for the next normal mode command.
+    pub pending: String,
+    pub register: Register,
+}
+
+impl Default for Vim {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Normal,
+            pending: String::new(),
+            register: Register::default(),
+        }
+    }
+}
+
+/// The modal keys, for the help screen.
+pub const HELP: &[&str] = &[
+    "",
+    "Modal editing (modal = true in the config):",
+    "i a I A o O          Insert before, after, at the line start or end, or on a new line",
+    "Esc                  Go back to normal mode",
+    "h j k l w b e 0 ^ $  Move, as often as the count typed before",
+    "gg G                 Go to the first or last line, or the line numbered by the count",
+    "d c y                Delete, change or yank up to a motion or over a text object",
+    "dd cc yy             Delete, change or yank lines",
+    "iw aw i( a( i[ i{ i\" Text objects: the word, or inside or around brackets and quotes",
+    "x p P                Delete characters, put after or before the cursor",
+    "v                    Select, then d, c or y the selection",
+    ":w :q :q! :wq        Save, quit, quit without saving, save and quit",
+];
+
+/// Parses the keys typed in normal mode: an optional count, then either an
+/// action or an operator with an optional count and a motion or text
+/// object.
+pub fn parse(keys: &str) -> Parse {
+    let (count, rest) = split_count(keys);
+    let mut chars = rest.chars();
+    let first = if let Some(first) = chars.next() {
+        first
+    } else {
+        return Parse::Pending;
+    };
+    let action = match first {
+        'i' => Action::Insert,
+        'a' => Action::Append,
+        'I' => Action::InsertAtLineStart,
+        'A' => Action::AppendAtLineEnd,
+        'o' => Action::OpenBelow,
+        'O' => Action::OpenAbove,
+        'x' => Action::DeleteChar,
+        'p' => Action::Put { before: false },
+        'P' => Action::Put { before: true },
+        'v' => Action::Visual,
+        ':' => Action::CommandLine,
+        'd' | 'c' | 'y' => {
+            let operator = match first {
+                'd' => Operator::Delete,
+                'c' => Operator::Change,
+                _ => Operator::Yank,
+            };
+            let (inner_count, rest) = split_count(chars.as_str());
+            let count = match (count, inner_count) {
+                (Some(outer), Some(inner)) => Some(outer.saturating_mul(inner).min(MAX_COUNT)),
+                (outer, inner) => outer.or(inner),
+            };
+            let target = if rest.len() == 1 && rest.starts_with(first) {
+                Target::Lines
+            } else {
+                match parse_target(rest) {
+                    Ok(Some(target)) => target,
+                    Ok(None) => return Parse::Pending,
+                    Err(()) => return Parse::Invalid,
+                }
+            };
+            return Parse::Done(count, Action::Operate(operator, target));
+        }
+        _ => {
+            return match parse_motion(rest) {
+                Ok(Some(motion)) => Parse::Done(count, Action::Move(motion)),
+                Ok(None) => Parse::Pending,
+                Err(()) => Parse::Invalid,
+            }
+        }
+    };
+    if chars.next().is_some() {
+        return Parse::Invalid;
+    }
+    Parse::Done(count, action)
+}
+
+/// Parses what follows an operator, giving `Ok(None)` for the start of
+/// it.
+fn parse_target(keys: &str) -> Result<Option<Target>, ()> {
+    let mut chars = keys.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (None, ..) => Ok(None),
+        (Some(kind @ ('i' | 'a')), object, None) => object.map_or(Ok(None), |c| {
+            let object = parse_object(c).ok_or(())?;
+            Ok(Some(Target::Object {
+                object,
+                inner: kind == 'i',
+            }))
+        }),
+        _ => Ok(parse_motion(keys)?.map(Target::Motion)),
+    }
+}
+
+/// Parses a motion, giving `Ok(None)` for the start of one.
+fn parse_motion(keys: &str) -> Result<Option<Motion>, ()> {
+    Ok(Some(match keys {
+        "h" => Motion::Left,
+        "l" | " " => Motion::Right,
+        "k" => Motion::Up,
+        "j" => Motion::Down,
+        "w" => Motion::WordStart,
+        "b" => Motion::WordBackward,
+        "e" => Motion::WordEnd,
+        "0" => Motion::LineStart,
+        "^" => Motion::FirstNonBlank,
+        "$" => Motion::LineEnd,
+        "gg" => Motion::FirstLine,
+        "G" => Motion::LastLine,
+        "g" => return Ok(None),
+        _ => return Err(()),
+    }))
+}
+
+fn parse_object(c: char) -> Option<Object> {
+    Some(match c {
+        'w' => Object::Word,
+        '(' | ')' | 'b' => Object::Pair('(', ')'),
+        '[' | ']' => Object::Pair('[', ']'),
+        '{' | '}' | 'B' => Object::Pair('{', '}'),
+        '<' | '>' => Object::Pair('<', '>'),
+        '"' | '\'' | '`' => Object::Quote(c),
+        _ => return None,
+    })
+}
+
+/// Splits a leading count off `keys`, at most `MAX_COUNT`. A `0` on its own
+/// is a motion, not a count.
+fn split_count(keys: &str) -> (Option<usize>, &str) {
+    let digits = if keys.starts_with('0') {
+        0
+    } else {
+        keys.chars().take_while(char::is_ascii_digit).count()
+    };
+    let (count, rest) = keys.split_at(digits);
+    // Digits too many to fit in a usize are a count over the maximum too.
+    let count = (digits > 0).then(|| count.parse().unwrap_or(MAX_COUNT).min(MAX_COUNT));
+    (count, rest)
+}
+
+/// Where `motion` goes from `at`, done `count` times. For `gg` and `G` the
+/// count is a line number instead.
+pub fn motion_target(
+    document: &Document,
+    motion: Motion,
+    count: Option<usize>,
+    at: &Position,
+) -> Position {
+    let last_line = document.len().saturating_sub(1);
+    match motion {
+        Motion::FirstLine | Motion::LastLine => {
+            let default = if motion == Motion::FirstLine {
+                1
+            } else {
+                document.len()
+            };
+            let y = count.unwrap_or(default).saturating_sub(1).min(last_line);
+            Position {
+                x: document.row(y).map_or(0, first_non_blank),
+                y,
+            }
+        }
+        _ => {
+            let mut position = at.clone();
+            for _ in 0..count.unwrap_or(1) {
+                let next = step(document, motion, &position);
+                // At the edge of the document further steps stay put too.
+                if next == position {
+                    break;
+                }
+                position = next;
+            }
+            position
+        }
+    }
+}
+
+fn step(document: &Document, motion: Motion, at: &Position) -> Position {
+    let Position { x, y } = *at;
+    let row_len = |y| document.row(y).map_or(0, Row::len);
+    let last_line = document.len().saturating_sub(1);
+    match motion {
+        Motion::Left => Position {
+            x: x.saturating_sub(1),
+            y,
+        },
+        Motion::Right => Position {
+            x: x.saturating_add(1).min(row_len(y)),
+            y,
+        },
+        Motion::Up | Motion::Down => {
+            let y = if motion == Motion::Up {
+                y.saturating_sub(1)
+            } else {
+                y.saturating_add(1).min(last_line)
+            };
+            Position {
+                x: x.min(row_len(y).saturating_sub(1)),
+                y,
+            }
+        }
+        Motion::WordStart => {
+            let words = document.row(y).map_or_else(Vec::new, Row::words);
+            if let Some(word) = words.iter().find(|word| word.start > x) {
+                return Position { x: word.start, y };
+            }
+            if y >= last_line {
+                return Position { x: row_len(y), y };
+            }
+            let y = y.saturating_add(1);
+            let words = document.row(y).map_or_else(Vec::new, Row::words);
+            Position {
+                x: words.first().map_or(0, |word| word.start),
+                y,
+            }
+        }
+        Motion::WordBackward => {
+            let words = document.row(y).map_or_else(Vec::new, Row::words);
+            if let Some(word) = words.iter().rev().find(|word| word.start < x) {
+                return Position { x: word.start, y };
+            }
+            if y == 0 {
+                return Position { x: 0, y };
+            }
+            let y = y.saturating_sub(1);
+            let words = document.row(y).map_or_else(Vec::new, Row::words);
+            Position {
+                x: words.last().map_or(0, |word| word.start),
+                y,
+            }
+        }
+        Motion::WordEnd => {
+            let mut y = y;
+            let mut x = x.saturating_add(1);
+            loop {
+                let words = document.row(y).map_or_else(Vec::new, Row::words);
+                if let Some(word) = words.iter().find(|word| word.end > x) {
+                    return Position {
+                        x: word.end.saturating_sub(1).max(x),
+                        y,
+                    };
+                }
+                if y >= last_line {
+                    return at.clone();
+                }
+                y = y.saturating_add(1);
+                x = 0;
+            }
+        }
+        Motion::LineStart => Position { x: 0, y },
+        Motion::FirstNonBlank => Position {
+            x: document.row(y).map_or(0, first_non_blank),
+            y,
+        },
+        Motion::LineEnd => Position {
+            x: row_len(y).saturating_sub(1),
+            y,
+        },
+        Motion::FirstLine | Motion::LastLine => motion_target(document, motion, None, at),
+    }
+}
+
+pub fn first_non_blank(row: &Row) -> usize {
+    row.leading_whitespace().graphemes(true).count()
+}
+
+/// The range of `object` around `at`, if there is one.
+pub fn object_range(
+    document: &Document,
+    object: Object,
+    inner: bool,
+    at: &Position,
+) -> Option<(Position, Position)> {
+    let row = document.row(at.y)?;
+    let y = at.y;
+    let range = match object {
+        Object::Word => word_object(row, at.x, inner),
+        Object::Quote(quote) => quote_object(row, at.x, quote, inner)?,
+        Object::Pair(open, close) => return pair_object(document, at, open, close, inner),
+    };
+    Some((Position { x: range.start, y }, Position { x: range.end, y }))
+}
+
+fn word_object(row: &Row, x: usize, inner: bool) -> Range<usize> {
+    let chars = row_chars(row);
+    let is_blank = |x: usize| chars.get(x).map_or(false, |c| c.is_whitespace());
+    let mut range = row.word_at(x);
+    if range.is_empty() {
+        // Between words, the object is the run of blanks.
+        let start = (0..x).rev().take_while(|x| is_blank(*x)).count();
+        let end = (x..chars.len()).take_while(|x| is_blank(*x)).count();
+        range = x.saturating_sub(start)..x.saturating_add(end);
+        if inner {
+            return range;
+        }
+        let word = row.word_at(range.end);
+        return range.start..word.end.max(range.end);
+    }
+    if inner {
+        return range;
+    }
+    let trailing = (range.end..chars.len())
+        .take_while(|x| is_blank(*x))
+        .count();
+    if trailing > 0 {
+        return range.start..range.end.saturating_add(trailing);
+    }
+    let leading = (0..range.start).rev().take_while(|x| is_blank(*x)).count();
+    range.start.saturating_sub(leading)..range.end
+}
+
+fn quote_object(row: &Row, x: usize, quote: char, inner: bool) -> Option<Range<usize>> {
+    let chars = row_chars(row);
+    let quotes: Vec<usize> = chars
+        .iter()
+        .enumerate()
+        .filter(|(index, c)| {
+            **c == quote && (*index == 0 || chars.get(index.saturating_sub(1)) != Some(&'\\'))
+        })
+        .map(|(index, _)| index)
+        .collect();
+    let (open, close) = quotes
+        .chunks(2)
+        .filter_map(|pair| match pair {
+            [open, close] => Some((*open, *close)),
+            _ => None,
+        })
+        .find(|(_, close)| *close >= x)?;
+    Some(if inner {
+        open.saturating_add(1)..close
+    } else {
+        open..close.saturating_add(1)
+    })
+}
+
+fn pair_object(
+    document: &Document,
+    at: &Position,
+    open: char,
+    close: char,
+    inner: bool,
+) -> Option<(Position, Position)> {
+    let char_at = |position: &Position| document.row(position.y)?.char_at(position.x);
+    // Walk back to the unmatched opener, then forward to its closer.
+    let mut start = at.clone();
+    let mut depth = 0_usize;
+    loop {
+        match char_at(&start) {
+            Some(c) if c == open && depth == 0 => break,
+            Some(c) if c == open => depth = depth.saturating_sub(1),
+            Some(c) if c == close && start != *at => depth = depth.saturating_add(1),
+            _ => (),
+        }
+        start = previous_position(document, &start)?;
+    }
+    let mut end = start.clone();
+    loop {
+        end = next_position(document, &end)?;
+        match char_at(&end) {
+            Some(c) if c == close && depth == 0 => break,
+            Some(c) if c == close => depth = depth.saturating_sub(1),
+            Some(c) if c == open => depth = depth.saturating_add(1),
+            _ => (),
+        }
+    }
+    Some(if inner {
+        (next_position(document, &start)?, end)
+    } else {
+        let after = Position {
+            x: end.x.saturating_add(1),
+            y: end.y,
+        };
+        (start, after)
+    })
+}
+
+/// The position of the character before `at`, stepping over line breaks.
+fn previous_position(document: &Document, at: &Position) -> Option<Position> {
+    if at.x > 0 {
+        return Some(Position {
+            x: at.x.saturating_sub(1).min(document.row(at.y)?.len()),
+            y: at.y,
+        });
+    }
+    let y = at.y.checked_sub(1)?;
+    Some(Position {
+        x: document.row(y)?.len(),
+        y,
+    })
+}
+
+/// The position of the character after `at`, stepping over line breaks.
+fn next_position(document: &Document, at: &Position) -> Option<Position> {
+    if at.x < document.row(at.y)?.len() {
+        return Some(Position {
+            x: at.x.saturating_add(1),
+            y: at.y,
+        });
+    }
+    let y = at.y.saturating_add(1);
+    document.row(y)?;
+    Some(Position { x: 0, y })
+}
+
+fn row_chars(row: &Row) -> Vec<char> {
+    row.as_str()
+        .graphemes(true)
+        .map(|grapheme| grapheme.chars().next().unwrap_or(' '))
+        .collect()
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("d"), Parse::Pending);
+        assert_eq!(parse("g"), Parse::Pending);
+        assert_eq!(parse("q"), Parse::Invalid);
+        assert_eq!(
+            parse("0"),
+            Parse::Done(None, Action::Move(Motion::LineStart))
+        );
+        assert_eq!(
+            parse("10G"),
+            Parse::Done(Some(10), Action::Move(Motion::LastLine))
+        );
+        assert_eq!(
+            parse("2d3w"),
+            Parse::Done(
+                Some(6),
+                Action::Operate(Operator::Delete, Target::Motion(Motion::WordStart))
+            )
+        );
+        assert_eq!(
+            parse("yy"),
+            Parse::Done(None, Action::Operate(Operator::Yank, Target::Lines))
+        );
+        assert_eq!(
+            parse("ci\""),
+            Parse::Done(
+                None,
+                Action::Operate(
+                    Operator::Change,
+                    Target::Object {
+                        object: Object::Quote('"'),
+                        inner: true
+                    }
+                )
+            )
+        );
+        assert_eq!(
+            parse("99999999999999999999999p"),
+            Parse::Done(Some(MAX_COUNT), Action::Put { before: false })
+        );
+        assert_eq!(
+            parse("500d500w"),
+            Parse::Done(
+                Some(MAX_COUNT),
+                Action::Operate(Operator::Delete, Target::Motion(Motion::WordStart))
+            )
+        );
+        assert_eq!(parse("ci"), Parse::Pending);
+        assert_eq!(parse("diq"), Parse::Invalid);
+    }
+
+    #[test]
+    fn test_motions_and_objects() {
+        let document = Document::scratch(&[
+            "fn main(a, b) {".to_string(),
+            "    say(\"hi there\");".to_string(),
+            "}".to_string(),
+        ]);
+        let at = |x, y| Position { x, y };
+        let go = |motion, count, from: Position| motion_target(&document, motion, count, &from);
+        assert_eq!(go(Motion::WordStart, None, at(0, 0)), at(3, 0));
+        assert_eq!(go(Motion::WordStart, Some(2), at(10, 0)), at(12, 0));
+        assert_eq!(go(Motion::WordStart, None, at(14, 0)), at(4, 1));
+        assert_eq!(go(Motion::WordEnd, None, at(0, 0)), at(1, 0));
+        assert_eq!(go(Motion::WordEnd, None, at(1, 0)), at(6, 0));
+        assert_eq!(go(Motion::WordBackward, None, at(4, 1)), at(14, 0));
+        assert_eq!(go(Motion::LineEnd, None, at(0, 1)), at(19, 1));
+        assert_eq!(go(Motion::LastLine, None, at(0, 0)), at(0, 2));
+        assert_eq!(go(Motion::FirstLine, Some(2), at(0, 0)), at(4, 1));
+        assert_eq!(go(Motion::WordStart, Some(MAX_COUNT), at(0, 0)), at(1, 2));
+
+        let object = |object, inner, from| object_range(&document, object, inner, &from);
+        assert_eq!(
+            object(Object::Quote('"'), true, at(4, 1)),
+            Some((at(9, 1), at(17, 1)))
+        );
+        assert_eq!(
+            object(Object::Pair('(', ')'), true, at(9, 0)),
+            Some((at(8, 0), at(12, 0)))
+        );
+        assert_eq!(
+            object(Object::Pair('{', '}'), false, at(5, 1)),
+            Some((at(14, 0), at(1, 2)))
+        );
+        assert_eq!(
+            object(Object::Word, false, at(10, 1)),
+            Some((at(9, 1), at(12, 1)))
+        );
+    }
+}
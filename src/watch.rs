@@ -0,0 +1,183 @@
+use std::convert::TryFrom;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// How often files are checked while the editor is idle.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// The size of an inotify event before the name that follows it.
+const EVENT_HEADER_BYTES: usize = 16;
+
+/// Tells when watched files may have changed on disk, through inotify where
+/// it is available and by asking for a check every `POLL_INTERVAL`
+/// otherwise. Callers compare the files with what they last read to see
+/// whether anything really changed, and call `changed` again by
+/// `next_check`.
+pub struct Watcher {
+    inotify: Option<File>,
+    /// The names of the watched files. Events for anything else in their
+    /// directories, such as swap and temporary files, are ignored.
+    names: Vec<OsString>,
+    last_poll: Instant,
+}
+
+impl Default for Watcher {
+    fn default() -> Self {
+        Self {
+            inotify: init_inotify(),
+            names: Vec::new(),
+            last_poll: Instant::now(),
+        }
+    }
+}
+
+impl Watcher {
+    /// Starts watching the file at `path`. Its directory is watched rather
+    /// than the file itself, so files replaced by a rename, as most editors
+    /// and `git checkout` do, are still noticed.
+    pub fn watch(&mut self, path: &Path) {
+        if let Some(name) = path.file_name() {
+            self.names.push(name.to_os_string());
+        }
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        if let Some(inotify) = &self.inotify {
+            if !add_watch(inotify, dir) {
+                self.inotify = None;
+            }
+        }
+    }
+    /// When `changed` is next worth calling.
+    pub fn next_check(&self) -> Instant {
+        self.last_poll + POLL_INTERVAL
+    }
+    /// Whether files may have changed since the last call.
+    pub fn changed(&mut self) -> bool {
+        if let Some(inotify) = &mut self.inotify {
+            self.last_poll = Instant::now();
+            let names = &self.names;
+            let mut changed = false;
+            let mut buffer = [0; 4096];
+            loop {
+                match inotify.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(length) => {
+                        let events = buffer.get(..length).unwrap_or_default();
+                        changed |= event_names(events)
+                            .iter()
+                            .any(|name| names.iter().any(|watched| watched == name));
+                    }
+                    Err(error) if error.kind() == ErrorKind::Interrupted => (),
+                    Err(_) => break,
+                }
+            }
+            return changed;
+        }
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+        true
+    }
+}
+
+/// The file names in a buffer of inotify events.
+fn event_names(mut events: &[u8]) -> Vec<&OsStr> {
+    let mut names = Vec::new();
+    while let Some(header) = events.get(..EVENT_HEADER_BYTES) {
+        let length = header
+            .get(12..)
+            .and_then(|length| <[u8; 4]>::try_from(length).ok())
+            .and_then(|length| usize::try_from(u32::from_ne_bytes(length)).ok())
+            .unwrap_or(0);
+        let end = EVENT_HEADER_BYTES.saturating_add(length);
+        let name = events.get(EVENT_HEADER_BYTES..end).unwrap_or_default();
+        // The name is padded with NUL bytes.
+        let name = name.split(|byte| *byte == 0).next().unwrap_or_default();
+        if !name.is_empty() {
+            names.push(OsStr::from_bytes(name));
+        }
+        events = events.get(end..).unwrap_or_default();
+    }
+    names
+}
+
+#[cfg(target_os = "linux")]
+fn init_inotify() -> Option<File> {
+    use std::os::unix::io::FromRawFd;
+    // SAFETY: inotify_init1 takes no pointers; the descriptor it returns is
+    // owned by the `File` from here on.
+    unsafe {
+        let fd = libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC);
+        if fd < 0 {
+            return None;
+        }
+        Some(File::from_raw_fd(fd))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn init_inotify() -> Option<File> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn add_watch(inotify: &File, dir: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::AsRawFd;
+    let dir = if let Ok(dir) = CString::new(dir.as_os_str().as_bytes()) {
+        dir
+    } else {
+        return false;
+    };
+    let mask = libc::IN_CLOSE_WRITE
+        | libc::IN_MOVED_TO
+        | libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MODIFY;
+    // SAFETY: `dir` is a valid NUL-terminated string that outlives the call.
+    unsafe { libc::inotify_add_watch(inotify.as_raw_fd(), dir.as_ptr(), mask) >= 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn add_watch(_inotify: &File, _dir: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod test_super {
+    use super::*;
+    #[cfg(target_os = "linux")]
+    use crate::tempdir::TempDir;
+    #[cfg(target_os = "linux")]
+    use std::fs;
+
+    #[test]
+    fn test_event_names() {
+        let mut events = vec![1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0];
+        events.extend_from_slice(&8_u32.to_ne_bytes());
+        events.extend_from_slice(b"a.txt\0\0\0");
+        events.extend_from_slice(&[0; 16]);
+        assert_eq!(event_names(&events), vec![OsStr::new("a.txt")]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_only_watched_files_count() {
+        let dir = TempDir::new("watch");
+        let file = dir.write("notes.txt", "one");
+        let mut watcher = Watcher::default();
+        watcher.watch(Path::new(&file));
+        dir.write(".notes.txt.hswp", "swap");
+        dir.write(".notes.txt.1.tmp", "temp");
+        assert!(!watcher.changed());
+        fs::write(&file, "two").unwrap();
+        assert!(watcher.changed());
+    }
+}